@@ -0,0 +1,83 @@
+use crate::game::{Base, Nature};
+use crate::production::Recipe;
+use crate::resources::ResourceRegistry;
+
+#[derive(Debug, Clone, Copy)]
+pub struct BasePolicy {
+    pub name: &'static str,
+    pub scout_share: f32,
+    pub explore_target: f32,
+    pub gatherers_per_resource: f32,
+    pub energy_reserve: u16,
+    pub max_robots: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct Observation {
+    pub base: Base,
+    pub explored: f32,
+    pub known_resources: usize,
+    pub scouts: usize,
+    pub gatherers: usize,
+    pub queued: usize,
+}
+
+impl BasePolicy {
+    pub fn from_str(policy_str: &str) -> Option<BasePolicy> {
+        match policy_str.to_lowercase().as_str() {
+            "balanced" => Some(BasePolicy {
+                name: "balanced",
+                scout_share: 0.25,
+                explore_target: 0.8,
+                gatherers_per_resource: 1.0,
+                energy_reserve: 20,
+                max_robots: 30,
+            }),
+            "explorer" => Some(BasePolicy {
+                name: "explorer",
+                scout_share: 0.5,
+                explore_target: 1.0,
+                gatherers_per_resource: 0.5,
+                energy_reserve: 10,
+                max_robots: 30,
+            }),
+            "harvester" => Some(BasePolicy {
+                name: "harvester",
+                scout_share: 0.15,
+                explore_target: 0.5,
+                gatherers_per_resource: 2.0,
+                energy_reserve: 30,
+                max_robots: 30,
+            }),
+            _ => None,
+        }
+    }
+
+    pub fn decide(&self, observation: &Observation, registry: &ResourceRegistry) -> Option<Nature> {
+        let robots = observation.scouts + observation.gatherers;
+        if observation.queued > 0 || robots >= self.max_robots {
+            return None;
+        }
+
+        let wants_scout = observation.explored < self.explore_target
+            && (observation.scouts as f32) < (robots + 1) as f32 * self.scout_share;
+        let wants_gatherer = (observation.gatherers as f32)
+            < observation.known_resources as f32 * self.gatherers_per_resource;
+        let nature = if wants_scout {
+            Nature::Scout
+        } else if wants_gatherer {
+            Nature::Gatherer
+        } else {
+            return None;
+        };
+
+        let mut cost = registry.resolve(Recipe::for_nature(nature).cost);
+        if let Some(fuel) = observation.base.fuel {
+            match cost.iter_mut().find(|(kind, _)| *kind == fuel) {
+                Some((_, amount)) => *amount = amount.saturating_add(self.energy_reserve),
+                None => cost.push((fuel, self.energy_reserve)),
+            }
+        }
+        observation.base.can_afford(&cost).then_some(nature)
+    }
+}
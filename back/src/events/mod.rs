@@ -1,10 +1,17 @@
 use crate::game::Localization;
+use crate::resources::{Cargo, ResourceKind};
 
+#[derive(Clone)]
 pub enum EventType {
     Tick,
     Moved(Localization),
-    Deposit((u16, u16)),
-    Collect((u16, u16)),
-    Extract(u32,(u16, f32)),
+    MoveRefused(Localization),
+    Yield,
+    Battery(u16),
+    Deposit(Cargo),
+    Collect((ResourceKind, u16)),
+    Extract(u32, (u16, f32), u16),
+    ResourceDepleted(u32),
+    ResourceDiscovered(u32),
     Nothing,
 }
\ No newline at end of file
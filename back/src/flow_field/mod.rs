@@ -0,0 +1,87 @@
+use std::collections::VecDeque;
+use crate::game::{Localization, Cell};
+use crate::topology::Topology;
+
+#[derive(Debug, Clone)]
+pub struct FlowField {
+    pub distances: Vec<Vec<Option<u32>>>,
+    pub topology: Topology,
+}
+
+const DIRECTIONS: [(i32, i32); 4] = [(-1, 0), (1, 0), (0, -1), (0, 1)];
+
+impl FlowField {
+    pub fn new(map_matrix: &[Vec<Cell>], target: Localization, display_obstacle: char, topology: Topology) -> Self {
+        Self::from_targets(map_matrix, &[target], display_obstacle, topology)
+    }
+
+    pub fn from_targets(map_matrix: &[Vec<Cell>], targets: &[Localization], display_obstacle: char, topology: Topology) -> Self {
+        Self::from_targets_where(map_matrix, targets, topology, |cell| cell.display != display_obstacle)
+    }
+
+    pub fn from_targets_where(map_matrix: &[Vec<Cell>], targets: &[Localization], topology: Topology, passable: impl Fn(&Cell) -> bool) -> Self {
+        let rows = map_matrix.len();
+        let cols = if rows > 0 { map_matrix[0].len() } else { 0 };
+        let mut distances = vec![vec![None; cols]; rows];
+
+        let mut queue: VecDeque<(u32, u32)> = VecDeque::new();
+        for target in targets {
+            if (target.x as usize) < rows && (target.y as usize) < cols {
+                distances[target.x as usize][target.y as usize] = Some(0);
+                queue.push_back((target.x, target.y));
+            }
+        }
+
+        while let Some((x, y)) = queue.pop_front() {
+            let distance = distances[x as usize][y as usize].unwrap_or(0);
+            for &(dx, dy) in &DIRECTIONS {
+                if let Some((new_x, new_y)) = topology.wrap(x as i32 + dx, y as i32 + dy, rows as u32, cols as u32) {
+                    let (new_x, new_y) = (new_x as usize, new_y as usize);
+                    if distances[new_x][new_y].is_none() && passable(&map_matrix[new_x][new_y]) {
+                        distances[new_x][new_y] = Some(distance + 1);
+                        queue.push_back((new_x as u32, new_y as u32));
+                    }
+                }
+            }
+        }
+
+        Self { distances, topology }
+    }
+
+    pub fn distance(&self, loc: Localization) -> Option<u32> {
+        self.distances
+            .get(loc.x as usize)
+            .and_then(|row| row.get(loc.y as usize))
+            .copied()
+            .flatten()
+    }
+
+    pub fn next_step(&self, loc: Localization) -> Option<Localization> {
+        let distance = self.distance(loc)?;
+        if distance == 0 {
+            return None;
+        }
+        let rows = self.distances.len() as u32;
+        let cols = self.distances.first().map_or(0, |row| row.len()) as u32;
+        for &(dx, dy) in &DIRECTIONS {
+            let Some(next) = self.topology.offset(loc, dx, dy, rows, cols) else {
+                continue;
+            };
+            if self.distance(next) == Some(distance - 1) {
+                return Some(next);
+            }
+        }
+        None
+    }
+
+    pub fn path_from(&self, loc: Localization) -> Option<Vec<Localization>> {
+        self.distance(loc)?;
+        let mut path = Vec::new();
+        let mut current = loc;
+        while let Some(next) = self.next_step(current) {
+            path.push(next);
+            current = next;
+        }
+        Some(path)
+    }
+}
@@ -1,15 +1,27 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::sync::mpsc::{Sender, Receiver, channel};
 use std::sync::{Arc, RwLock};
-use noise::{NoiseFn, Perlin};
+use noise::NoiseFn;
 use rand::prelude::*;
 use std::f64;
 use std::thread;
+use serde::Serialize;
 use crate::id_generator::IDGenerator;
 use crate::gatherers::*;
 use crate::scouts::*;
 use crate::resources::*;
 use crate::events::*;
+use crate::flow_field::FlowField;
+use crate::planner::{SpaceTimeTable, WINDOW};
+use crate::production::{BuildOrder, BuildState, Recipe, MAX_QUEUE};
+use crate::controller::{BasePolicy, Observation};
+use crate::upgrades::{UpgradeKind, Upgrades};
+use crate::ledger::{Ledger, Operation};
+use crate::terrain::TerrainSettings;
+use crate::map_generator::{BasePlacement, GeneratorKind, Reachability};
+use crate::level::{Legend, Level, LevelError, LevelResource, LevelRobot};
+use crate::metrics::MapMetrics;
+use crate::topology::Topology;
 
 pub struct Game {
     pub cols: u32,
@@ -20,9 +32,32 @@ pub struct Game {
     pub receivers: HashMap<u32, Receiver<EventType>>,
     pub resources: Arc<RwLock<HashMap<u32, Resource>>>,
     pub finded_resources: Arc<RwLock<Vec<u32>>>,
+    pub reservations: Arc<RwLock<HashMap<u32, u32>>>,
+    pub frontier_claims: Arc<RwLock<HashMap<u32, Localization>>>,
+    pub exploration_mode: ExplorationMode,
+    pub base_stacking: bool,
+    pub planner: Arc<RwLock<SpaceTimeTable>>,
+    pub battery_capacity: u16,
+    pub id_generator: IDGenerator,
+    pub build_queue: VecDeque<BuildOrder>,
+    pub controller: Option<BasePolicy>,
+    pub upgrades: Arc<RwLock<Upgrades>>,
+    pub registry: ResourceRegistry,
+    pub resource_rules: Vec<ResourceRule>,
+    pub pending_respawns: Vec<(u32, ResourceKind, u32)>,
+    pub ledger: Ledger,
+    pub terrain: TerrainSettings,
+    pub generator: GeneratorKind,
+    pub reachability: Reachability,
+    pub base_placement: BasePlacement,
+    pub topology: Topology,
+    pub spawn_points: Vec<Localization>,
+    pub generated_reachable_fraction: Option<f32>,
     pub map_matrix: Arc<RwLock<Vec<Vec<Cell>>>>,
+    pub base_flow: Arc<RwLock<FlowField>>,
     pub age: u32,
-    pub base: Base,
+    pub step: u32,
+    pub base: Arc<RwLock<Base>>,
     pub display_void: char,
     pub display_obstacle: char,
     pub display_base: char,
@@ -30,21 +65,87 @@ pub struct Game {
     pub display_gatherer: char,
 }
 
-enum Nature {
+#[derive(Debug, Clone, Copy)]
+pub enum Nature {
     Gatherer,
     Scout
 }
 
-struct Robot {
+impl Nature {
+    pub fn from_str(nature_str: &str) -> Option<Nature> {
+        match nature_str.to_lowercase().as_str() {
+            "gatherer" => Some(Nature::Gatherer),
+            "scout" => Some(Nature::Scout),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Nature::Gatherer => "gatherer",
+            Nature::Scout => "scout",
+        }
+    }
+}
+
+pub struct Robot {
     nature: Nature,
-    loc: Localization
+    loc: Localization,
+    cargo: u16,
+    waiting: u32,
+    battery: u16,
+    disabled: bool,
+    idle: bool,
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Serialize)]
+pub struct RobotState {
+    pub id: u32,
+    pub kind: &'static str,
+    pub x: u32,
+    pub y: u32,
+    pub battery: u16,
+    pub disabled: bool,
+    pub idle: bool,
+}
+
+impl Robot {
+    fn new(nature: Nature, loc: Localization, battery: u16) -> Self {
+        Self {
+            nature,
+            loc,
+            cargo: 0,
+            waiting: 0,
+            battery,
+            disabled: false,
+            idle: false,
+        }
+    }
+
+    fn priority(&self, id: u32) -> u64 {
+        let class: u64 = match self.nature {
+            Nature::Gatherer if self.cargo > 0 => 2,
+            Nature::Gatherer => 1,
+            Nature::Scout => 0,
+        };
+        (class << 32) | (u32::MAX - id) as u64
+    }
+}
+
+const DEADLOCK_AFTER: u32 = 5;
+pub const MOVE_COST: u16 = 1;
+pub const EXTRACT_COST: u16 = 2;
+pub const RECHARGE_RATE: u16 = 20;
+pub const RECHARGE_MARGIN: u16 = 10;
+const BATTERY_PER_ENERGY: u16 = 10;
+const VEIN_SCALE: f64 = 6.0;
+const MAX_RESPAWN_FAILURES: u32 = 5;
+
+#[derive(Debug, Clone)]
 pub struct Base {
     pub loc: Localization,
-    pub crystal: u16,
-    pub energy: u16,
+    pub stock: Stock,
+    pub fuel: Option<ResourceKind>,
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -66,17 +167,58 @@ pub struct Cell {
 }
 
 impl Base {
-    pub fn new(rows: u32, cols: u32) -> Self {
-        let loc = Localization{x: ((rows + 1) / 2), y: ((cols + 1) / 2)};
+    pub fn new(rows: u32, cols: u32, registry: &ResourceRegistry) -> Self {
+        let loc = Localization{x: rows / 2, y: cols / 2};
         Self {
             loc,
-            crystal: 0,
-            energy: 0,
+            stock: registry.empty_stock(),
+            fuel: registry.fuel(),
+        }
+    }
+
+    pub fn most_needed_kind(&self) -> ResourceKind {
+        let index = self.stock.iter()
+            .enumerate()
+            .min_by_key(|&(_, amount)| *amount)
+            .map_or(0, |(index, _)| index);
+        ResourceKind(index)
+    }
+
+    pub fn amount(&self, kind: ResourceKind) -> u64 {
+        self.stock.get(kind.0).copied().unwrap_or(0)
+    }
+
+    pub fn fuel_stock(&self) -> u64 {
+        self.fuel.map_or(0, |kind| self.amount(kind))
+    }
+
+    pub fn keeps_charging(&self, battery: u16, battery_capacity: u16) -> bool {
+        battery < battery_capacity && (self.fuel_stock() > 0 || self.waits_for_fuel(battery, battery_capacity))
+    }
+
+    pub fn waits_for_fuel(&self, battery: u16, battery_capacity: u16) -> bool {
+        self.fuel_stock() == 0 && battery < battery_capacity / 2
+    }
+
+    pub fn can_afford(&self, cost: &[(ResourceKind, u16)]) -> bool {
+        cost.iter().all(|&(kind, amount)| self.amount(kind) >= amount as u64)
+    }
+
+    pub fn pay(&mut self, cost: &[(ResourceKind, u16)]) {
+        for &(kind, amount) in cost {
+            self.stock[kind.0] = self.stock[kind.0].saturating_sub(amount as u64);
+        }
+    }
+
+    pub fn deposit(&mut self, cargo: &[u16]) {
+        for (stored, amount) in self.stock.iter_mut().zip(cargo.iter()) {
+            *stored = stored.saturating_add(*amount as u64);
         }
     }
 }
 
 impl Game {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(rows: u32, cols: u32, seed: u64, display_void: char, display_obstacle: char, display_base: char, display_scout: char, display_gatherer: char) -> Self {
         let robots = HashMap::new();
         let senders = HashMap::new();
@@ -84,6 +226,8 @@ impl Game {
         let resources = HashMap::new();
         let mut map_matrix = Vec::new();
         let finded_resources = Vec::new();
+        let reservations = HashMap::new();
+        let frontier_claims = HashMap::new();
         for _ in 0..rows {
             let mut row = Vec::new();
             for _ in 0..cols {
@@ -91,6 +235,9 @@ impl Game {
             }
             map_matrix.push(row);
         }
+        let registry = ResourceRegistry::default();
+        let base = Base::new(rows, cols, &registry);
+        let base_flow = FlowField::new(&map_matrix, base.loc, display_obstacle, Topology::Bounded);
         Self {
             rows,
             cols,
@@ -100,9 +247,32 @@ impl Game {
             receivers,
             resources: Arc::new(RwLock::new(resources)),
             finded_resources: Arc::new(RwLock::new(finded_resources)),
+            reservations: Arc::new(RwLock::new(reservations)),
+            frontier_claims: Arc::new(RwLock::new(frontier_claims)),
+            exploration_mode: ExplorationMode::Local,
+            base_stacking: true,
+            planner: Arc::new(RwLock::new(SpaceTimeTable::new())),
+            battery_capacity: 150,
+            id_generator: IDGenerator::new(),
+            build_queue: VecDeque::new(),
+            controller: None,
+            upgrades: Arc::new(RwLock::new(Upgrades::default())),
+            resource_rules: vec![ResourceRule::default(); registry.len()],
+            registry,
+            pending_respawns: Vec::new(),
+            ledger: Ledger::default(),
+            terrain: TerrainSettings::default(),
+            generator: GeneratorKind::Perlin,
+            reachability: Reachability::Carve,
+            base_placement: BasePlacement::Center,
+            topology: Topology::Bounded,
+            spawn_points: Vec::new(),
+            generated_reachable_fraction: None,
             map_matrix: Arc::new(RwLock::new(map_matrix)),
+            base_flow: Arc::new(RwLock::new(base_flow)),
             age: 0,
-            base: Base::new(rows, cols),
+            step: 0,
+            base: Arc::new(RwLock::new(base)),
             display_void,
             display_obstacle,
             display_base,
@@ -111,33 +281,42 @@ impl Game {
         }
     }
 
+    pub fn set_registry(&mut self, registry: ResourceRegistry) {
+        let mut base = self.base.write().unwrap();
+        base.stock = registry.empty_stock();
+        base.fuel = registry.fuel();
+        drop(base);
+        self.resource_rules = vec![ResourceRule::default(); registry.len()];
+        self.ledger = Ledger::default();
+        self.registry = registry;
+    }
+
     pub fn add_scout(
         &mut self,
         x: u32,
         y: u32,
-        id_generator: &mut IDGenerator
     ) {
         let loc = Localization { x, y };
 
-        if let Some(mut scout) = Scout::new(loc, id_generator) {
+        if let Some(mut scout) = Scout::new(loc, self.battery_capacity, self.topology, &mut self.id_generator) {
             let (map_sender, map_receiver): (Sender<EventType>, Receiver<EventType>) = channel();
             let (scout_sender, scout_receiver): (Sender<EventType>, Receiver<EventType>) = channel();
             let map_matrix = Arc::clone(&self.map_matrix);
             let rows = self.rows;
             let cols = self.cols;
             let seed = self.seed;
+            let exploration_mode = self.exploration_mode;
+            let frontier_claims = Arc::clone(&self.frontier_claims);
+            let planner = Arc::clone(&self.planner);
+            let base_flow = Arc::clone(&self.base_flow);
+            let base = Arc::clone(&self.base);
             let display_obstacle = self.display_obstacle;
 
             self.senders.insert(scout.id, scout_sender);
             self.receivers.insert(scout.id, map_receiver);
-            self.robots.insert(
-                scout.id, Robot {
-                    nature: Nature::Scout,
-                    loc: scout.loc,
-                }
-            );
+            self.robots.insert(scout.id, Robot::new(Nature::Scout, scout.loc, self.battery_capacity));
             thread::spawn(move || {
-                scout.handle_events(map_matrix, rows, cols, seed, scout_receiver, map_sender, display_obstacle);
+                scout.handle_events(map_matrix, rows, cols, seed, exploration_mode, frontier_claims, planner, base_flow, base, scout_receiver, map_sender, display_obstacle);
             });
 
         }
@@ -147,137 +326,357 @@ impl Game {
         &mut self,
         x: u32,
         y: u32,
-        id_generator: &mut IDGenerator
     ) {
         let loc = Localization { x, y };
 
-        if let Some(mut gatherer) = Gatherer::new(loc, id_generator) {
+        if let Some(mut gatherer) = Gatherer::new(loc, self.battery_capacity, self.registry.cargo_limits(), self.topology, &mut self.id_generator) {
             let (map_sender, map_receiver): (Sender<EventType>, Receiver<EventType>) = channel();
             let (gatherer_sender, gatherer_receiver): (Sender<EventType>, Receiver<EventType>) = channel();
             let map_matrix = Arc::clone(&self.map_matrix);
             
             let resources = Arc::clone(&self.resources);
             let finded_resources = Arc::clone(&self.finded_resources);
-            let base_loc = self.base.loc;
+            let reservations = Arc::clone(&self.reservations);
+            let planner = Arc::clone(&self.planner);
+            let upgrades = Arc::clone(&self.upgrades);
+            let base_flow = Arc::clone(&self.base_flow);
+            let base = Arc::clone(&self.base);
             let seed = self.seed;
             let display_obstacle = self.display_obstacle;
             self.senders.insert(gatherer.id, gatherer_sender);
             self.receivers.insert(gatherer.id, map_receiver);
-            self.robots.insert(gatherer.id,
-                Robot {
-                    nature: Nature::Gatherer,
-                    loc: gatherer.loc,
-                }
-            );
+            self.robots.insert(gatherer.id, Robot::new(Nature::Gatherer, gatherer.loc, self.battery_capacity));
             thread::spawn(move || {
-                gatherer.handle_events(map_matrix, resources, base_loc, seed, finded_resources, gatherer_receiver, map_sender, display_obstacle);
+                gatherer.handle_events(map_matrix, resources, base_flow, base, seed, finded_resources, reservations, planner, upgrades, gatherer_receiver, map_sender, display_obstacle);
             });
 
         }
     }
 
+    pub fn set_resource_rules(&mut self, rules: HashMap<String, ResourceRule>) {
+        self.resource_rules = vec![ResourceRule::default(); self.registry.len()];
+        for (name, rule) in rules {
+            match self.registry.kind(&name) {
+                Some(kind) => self.resource_rules[kind.0] = rule,
+                None => eprintln!("Unknown resource kind : {}", name),
+            }
+        }
+    }
+
     pub fn add_resource(
         &mut self,
         resource_kind_str: &str,
-        initial_quantity: u16,
-        id_generator: &mut IDGenerator
-    ) {
-        if let Some(kind) = ResourceKind::from_str(resource_kind_str) {
-            let loc = self.find_free_localization();
-            if let Some(resource) = Resource::new_resource(loc, kind, initial_quantity, id_generator) {
-                let mut resources = self.resources.write().unwrap();
-                resources.insert(resource.id, resource);
+    ) -> Result<u32, PlacementError> {
+        let kind = self.registry.kind(resource_kind_str)
+            .ok_or_else(|| PlacementError::UnknownKind(resource_kind_str.to_string()))?;
+        let rule = self.resource_rules.get(kind.0).cloned().unwrap_or_default();
+        let loc = self.find_free_localization(kind, &rule)?;
+        let initial_quantity = self.initial_quantity(kind, &rule);
+        self.insert_resource(loc, kind, initial_quantity)
+    }
+
+    fn initial_quantity(&self, kind: ResourceKind, rule: &ResourceRule) -> u16 {
+        match rule.quantity {
+            Some((min, max)) => {
+                let count = self.resources.read().unwrap().len() as u64;
+                let mut rng = StdRng::seed_from_u64(self.seed.wrapping_add(count * 7919));
+                rng.gen_range(min.min(max)..=max.max(min))
             }
-        } else {
-            eprintln!("Unknown resource kind : {}", resource_kind_str);
+            None => self.registry.get(kind).quantity,
         }
     }
 
-    pub fn find_free_localization(&self) -> Localization {
-        let mut rng = StdRng::seed_from_u64(self.seed.wrapping_add(
-            self.age.pow(2) as u64 * 13
-        ));
-        let mut loc = Localization { x: 0, y: 0 };
-        loop {
-            rng = StdRng::seed_from_u64(rng.gen::<u64>().wrapping_add(11));
-            let x = rng.gen_range(0..self.rows);
-            let y = rng.gen_range(0..self.cols);
-            let map_matrix = self.map_matrix.read().unwrap(); 
-            let cell = &map_matrix[x as usize][y as usize];
-    
-            if cell.display != self.display_base && cell.display != self.display_obstacle {
-                let mut is_free = true;
-                let resources = self.resources.read().unwrap();
-                for resource in resources.values() {
-                    if resource.loc.x == x && resource.loc.y == y {
-                        is_free = false;
-                        break;
-                    }
+    fn insert_resource(&mut self, loc: Localization, kind: ResourceKind, initial_quantity: u16) -> Result<u32, PlacementError> {
+        let resource_type = self.registry.get(kind);
+        let resource = Resource::new_resource(loc, kind, resource_type, initial_quantity, &mut self.id_generator)
+            .ok_or_else(|| PlacementError::NoFreeCell(resource_type.name.clone()))?;
+        self.resources.write().unwrap().insert(resource.id, resource);
+        Ok(resource.id)
+    }
+
+    pub fn load_level(&mut self, level: &Level) -> Result<(), LevelError> {
+        level.validate(&self.registry)?;
+        {
+            let mut map_matrix = self.map_matrix.write().unwrap();
+            for &(x, y) in level.obstacles.iter() {
+                map_matrix[x as usize][y as usize].display = self.display_obstacle;
+            }
+            for &(x, y) in level.base.iter() {
+                map_matrix[x as usize][y as usize].display = self.display_base;
+                map_matrix[x as usize][y as usize].explore = 30;
+            }
+        }
+        let count = level.base.len() as f64;
+        let center_x = level.base.iter().map(|&(x, _)| x as f64).sum::<f64>() / count;
+        let center_y = level.base.iter().map(|&(_, y)| y as f64).sum::<f64>() / count;
+        let base_loc = level.base.iter()
+            .min_by(|a, b| {
+                let distance = |&(x, y): &(u32, u32)| (x as f64 - center_x).powi(2) + (y as f64 - center_y).powi(2);
+                distance(a).total_cmp(&distance(b))
+            })
+            .map(|&(x, y)| Localization { x, y })
+            .ok_or(LevelError::MissingBase)?;
+        self.base.write().unwrap().loc = base_loc;
+        self.update_base_flow();
+
+        let positions = level.resources.iter().map(|resource| (resource.x, resource.y))
+            .chain(level.spawns.iter().copied())
+            .chain(level.robots.iter().map(|robot| (robot.x, robot.y)));
+        {
+            let base_flow = self.base_flow.read().unwrap();
+            for (x, y) in positions {
+                if base_flow.distance(Localization { x, y }).is_none() {
+                    return Err(LevelError::Unreachable(x, y));
                 }
+            }
+        }
+
+        for resource in level.resources.iter() {
+            let kind = self.registry.kind(&resource.kind)
+                .ok_or_else(|| LevelError::UnknownResource(resource.kind.clone()))?;
+            let rule = self.resource_rules.get(kind.0).cloned().unwrap_or_default();
+            let quantity = resource.quantity.unwrap_or_else(|| self.initial_quantity(kind, &rule));
+            let loc = Localization { x: resource.x, y: resource.y };
+            self.insert_resource(loc, kind, quantity)
+                .map_err(|_| LevelError::Overlap(resource.x, resource.y))?;
+        }
+        self.spawn_points = level.spawns.iter().map(|&(x, y)| Localization { x, y }).collect();
+        if self.spawn_points.is_empty() {
+            self.spawn_points.push(base_loc);
+        }
+        Ok(())
+    }
 
+    pub fn add_level_robots(&mut self, level: &Level) -> Result<(), LevelError> {
+        for robot in level.robots.iter() {
+            match Nature::from_str(&robot.kind) {
+                Some(Nature::Scout) => self.add_scout(robot.x, robot.y),
+                Some(Nature::Gatherer) => self.add_gatherer(robot.x, robot.y),
+                None => return Err(LevelError::UnknownRobot(robot.kind.clone())),
+            }
+        }
+        Ok(())
+    }
+
+    pub fn legend(&self) -> Legend {
+        Legend {
+            empty: self.display_void,
+            obstacle: self.display_obstacle,
+            base: self.display_base,
+            scout: self.display_scout,
+            gatherer: self.display_gatherer,
+        }
+    }
+
+    pub fn export_level(&self) -> Level {
+        let map_matrix = self.map_matrix.read().unwrap();
+        let mut obstacles = Vec::new();
+        let mut base = Vec::new();
+        for x in 0..self.rows {
+            for y in 0..self.cols {
+                let display = map_matrix[x as usize][y as usize].display;
+                if display == self.display_obstacle {
+                    obstacles.push((x, y));
+                } else if display == self.display_base {
+                    base.push((x, y));
+                }
+            }
+        }
+        let mut resources: Vec<LevelResource> = self.resources.read().unwrap().values()
+            .map(|resource| LevelResource {
+                kind: self.registry.get(resource.kind).name.clone(),
+                x: resource.loc.x,
+                y: resource.loc.y,
+                quantity: Some(resource.remaining_quantity),
+            })
+            .collect();
+        resources.sort_by_key(|resource| (resource.x, resource.y));
+        let robots = self.robot_states().into_iter()
+            .filter(|state| !state.disabled)
+            .map(|state| LevelRobot { kind: state.kind.to_string(), x: state.x, y: state.y })
+            .collect();
+        let base_loc = self.base.read().unwrap().loc;
+        let spawns = if self.spawn_points.iter().all(|loc| loc.same_loc(&base_loc)) {
+            Vec::new()
+        } else {
+            self.spawn_points.iter().map(|loc| (loc.x, loc.y)).collect()
+        };
+        Level {
+            rows: self.rows,
+            columns: self.cols,
+            obstacles,
+            base,
+            resources,
+            spawns,
+            robots,
+        }
+    }
+
+    pub fn map_metrics(&self) -> MapMetrics {
+        let map_matrix = self.map_matrix.read().unwrap();
+        let base_flow = self.base_flow.read().unwrap();
+        let resources: Vec<Resource> = self.resources.read().unwrap().values().copied().collect();
+        let mut metrics = MapMetrics::measure(&map_matrix, &base_flow, &resources, &self.registry, self.display_obstacle);
+        if let Some(fraction) = self.generated_reachable_fraction {
+            metrics.reachable_fraction = fraction;
+        }
+        metrics
+    }
+
+    pub fn generate_terrain_display(&self) -> Vec<Vec<char>> {
+        let mut result_map: Vec<Vec<char>> = self.map_matrix.read().unwrap().iter()
+            .map(|row| row.iter().map(|cell| cell.display).collect())
+            .collect();
+        for resource in self.resources.read().unwrap().values() {
+            result_map[resource.loc.x as usize][resource.loc.y as usize] = resource.display;
+        }
+        result_map
+    }
+
+    pub fn robot_spawn(&self, index: usize) -> Option<Localization> {
+        let count = self.spawn_points.len();
+        let preferred: Vec<Localization> = (0..count)
+            .map(|offset| self.spawn_points[(index + offset) % count])
+            .collect();
+        self.free_spawn(&preferred)
+    }
+
+    pub fn placement_candidates(&self, rule: &ResourceRule) -> Vec<Localization> {
+        let map_matrix = self.map_matrix.read().unwrap();
+        let base_flow = self.base_flow.read().unwrap();
+        let resources = self.resources.read().unwrap();
+        let spacing = rule.spacing.max(1);
+        let mut candidates = Vec::new();
+        for x in 0..self.rows {
+            for y in 0..self.cols {
+                let cell = &map_matrix[x as usize][y as usize];
+                if cell.display == self.display_base || cell.display == self.display_obstacle {
+                    continue;
+                }
+                let loc = Localization { x, y };
+                if self.spawn_points.iter().any(|spawn| spawn.same_loc(&loc)) {
+                    continue;
+                }
+                let Some(base_distance) = base_flow.distance(loc) else {
+                    continue;
+                };
+                if rule.min_base_distance.is_some_and(|min| base_distance < min) {
+                    continue;
+                }
+                if rule.max_base_distance.is_some_and(|max| base_distance > max) {
+                    continue;
+                }
+                let is_free = resources.values().all(|resource| {
+                    self.topology.manhattan(resource.loc, loc, self.rows, self.cols) >= spacing
+                });
                 if is_free {
-                    loc = Localization { x, y };
-                    break;
+                    candidates.push(loc);
                 }
             }
         }
-        return loc;
+        candidates
     }
 
-    pub fn update_explore_matrix(&mut self) {
+    pub fn find_free_localization(&self, kind: ResourceKind, rule: &ResourceRule) -> Result<Localization, PlacementError> {
+        let candidates = self.placement_candidates(rule);
+        if candidates.is_empty() {
+            return Err(PlacementError::NoFreeCell(self.registry.get(kind).name.clone()));
+        }
+        let count = self.resources.read().unwrap().len() as u64;
+        let mut rng = StdRng::seed_from_u64(self.seed
+            .wrapping_add((self.age as u64).wrapping_mul(self.age as u64).wrapping_mul(13))
+            .wrapping_add(count * 104729));
+        if rule.veins > 0.0 {
+            let fbm = self.terrain.noise(self.seed);
+            let weighted = candidates.choose_weighted(&mut rng, |loc| {
+                let noise = fbm.get([
+                    loc.x as f64 / VEIN_SCALE + kind.0 as f64 * 31.7,
+                    loc.y as f64 / VEIN_SCALE,
+                ]);
+                ((noise + 1.0) / 2.0).clamp(0.0, 1.0).powf(rule.veins as f64 * 8.0)
+            });
+            if let Ok(loc) = weighted {
+                return Ok(*loc);
+            }
+        }
+        Ok(candidates[rng.gen_range(0..candidates.len())])
+    }
 
-    
+    pub fn update_explore_matrix(&mut self) -> Vec<u32> {
+        let mut discovered = Vec::new();
+        let radius = self.upgrades.read().unwrap().vision_radius();
         let mut map_matrix = self.map_matrix.write().unwrap();
         let mut finded_resources = self.finded_resources.write().unwrap();
         
         for robot in self.robots.values() {
-            match robot.nature {
-                Nature::Scout => {
-                    let x = robot.loc.x as i32;
-                    let y = robot.loc.y as i32;
-                    for delta_x in -1..=1 {
-                        for delta_y in -1..=1 {
-                            let dx = x + delta_x;
-                            let dy = y + delta_y;
-    
-                            if dx >= 0 && dx < self.rows as i32 && dy >= 0 && dy < self.cols as i32 {
-                                map_matrix[dx as usize][dy as usize].explore = 30;
-                                if let Some(resource) = self.find_resource_by_loc(dx as u32, dy as u32) {
-                                    if !finded_resources.contains(&resource.id) {
-                                        finded_resources.push(resource.id);
-                                    }
+            if let Nature::Scout = robot.nature {
+                let x = robot.loc.x as i32;
+                let y = robot.loc.y as i32;
+                for delta_x in -radius..=radius {
+                    for delta_y in -radius..=radius {
+                        if let Some((dx, dy)) = self.topology.wrap(x + delta_x, y + delta_y, self.rows, self.cols) {
+                            map_matrix[dx as usize][dy as usize].explore = 30;
+                            if let Some(resource) = self.find_resource_by_loc(dx, dy) {
+                                if !finded_resources.contains(&resource.id) {
+                                    finded_resources.push(resource.id);
+                                    discovered.push(resource.id);
                                 }
                             }
                         }
                     }
                 }
-                _ => {}
             }
         }
+        discovered
     }
 
     pub fn decay_passage_counters(&mut self) {
-        let center_x = self.rows / 2;
-        let center_y = self.cols / 2;
         let mut map_matrix = self.map_matrix.write().unwrap();
     
         for row in 0..self.rows as usize {
             for col in 0..self.cols as usize {
-                if !(row >= (center_x - 1) as usize && row <= (center_x + 1) as usize &&
-                     col >= (center_y - 1) as usize && col <= (center_y + 1) as usize) {
-                    if map_matrix[row][col].explore > 0 {
-                        map_matrix[row][col].explore -= 1;
-                    }
+                let cell = &mut map_matrix[row][col];
+                if cell.display != self.display_base && cell.explore > 0 {
+                    cell.explore -= 1;
+                }
+            }
+        }
+    }
+
+    pub fn update_base_flow(&mut self) {
+        let map_matrix = self.map_matrix.read().unwrap();
+        let mut base_tiles = Vec::new();
+        for x in 0..self.rows {
+            for y in 0..self.cols {
+                if map_matrix[x as usize][y as usize].display == self.display_base {
+                    base_tiles.push(Localization { x, y });
+                }
+            }
+        }
+        let mut base_flow = self.base_flow.write().unwrap();
+        *base_flow = FlowField::from_targets(&map_matrix, &base_tiles, self.display_obstacle, self.topology);
+    }
+
+    pub fn generate_base_distance_layer(&self) -> Vec<Vec<Option<u32>>> {
+        let map_matrix = self.map_matrix.read().unwrap();
+        let base_flow = self.base_flow.read().unwrap();
+        let mut layer = base_flow.distances.clone();
+
+        for x in 0..self.rows as usize {
+            for y in 0..self.cols as usize {
+                if map_matrix[x][y].explore == -1 {
+                    layer[x][y] = None;
                 }
             }
         }
+        layer
     }
 
     pub fn find_resource_by_loc(&self, x: u32, y: u32) -> Option<Resource> {
         let resources = self.resources.read().unwrap();
         for resource in resources.values() {
             if resource.loc.x == x && resource.loc.y == y {
-                return Some(resource.clone());
+                return Some(*resource);
             }
         }
         None
@@ -286,38 +685,378 @@ impl Game {
         if let EventType::Tick = event {
             self.age += 1;
     
-            for tx in self.senders.values() {
+            let mut order: Vec<u32> = self.robots.iter()
+                .filter(|(_, robot)| !robot.disabled)
+                .map(|(id, _)| *id)
+                .collect();
+            order.sort_by_key(|id| std::cmp::Reverse(self.robots[id].priority(*id)));
+
+            let substeps = self.upgrades.read().unwrap().moves_per_tick();
+            let mut refused = Vec::new();
+            for _ in 0..substeps {
+                if order.is_empty() {
+                    break;
+                }
+                let moves = self.step_robots(&order);
+                let step_refused = self.resolve_moves(moves.clone());
+                order = moves.iter()
+                    .map(|(id, _)| *id)
+                    .filter(|id| !step_refused.iter().any(|(refused_id, _)| refused_id == id))
+                    .collect();
+                refused.extend(step_refused);
+            }
+            self.detect_deadlocks(&refused);
+            self.run_controller();
+            self.advance_production();
+            self.recharge_robots();
+            let depleted = self.clear_empty_resources();
+            self.regrow_resources();
+            self.respawn_resources();
+            self.decay_passage_counters();
+            let discovered = self.update_explore_matrix();
+
+            for id in depleted {
+                self.notify_gatherers(EventType::ResourceDepleted(id));
+            }
+            for id in discovered {
+                self.notify_gatherers(EventType::ResourceDiscovered(id));
+            }
+        }
+    }
+
+    fn step_robots(&mut self, order: &[u32]) -> Vec<(u32, Localization)> {
+        self.step += 1;
+        {
+            let map_matrix = self.map_matrix.read().unwrap();
+            let mut planner = self.planner.write().unwrap();
+            planner.advance(self.step, self.base_stacking.then_some(self.display_base));
+            for id in order.iter() {
+                planner.set_priority(*id, self.robots[id].priority(*id));
+            }
+            for (id, robot) in self.robots.iter().filter(|(_, robot)| robot.disabled) {
+                planner.set_priority(*id, u64::MAX);
+                for t in self.step..=self.step + WINDOW {
+                    planner.hold(*id, robot.loc, t, &map_matrix);
+                }
+            }
+        }
+
+        let mut moves = Vec::new();
+        for id in order.iter() {
+            if let Some(tx) = self.senders.get(id) {
                 let _ = tx.send(EventType::Tick);
             }
-    
-            for (id, rx) in self.receivers.iter() {
-                if let Ok(response) = rx.recv() {
-                    match response {
-                        EventType::Moved(new_loc) => {
-                            if let Some(robot) = self.robots.get_mut(id) {
-                                robot.loc = new_loc;
-                            } 
+            if let Some(Ok(response)) = self.receivers.get(id).map(|rx| rx.recv()) {
+                match response {
+                    EventType::Moved(new_loc) => {
+                        moves.push((*id, new_loc));
+                    }
+                    EventType::Deposit(cargo) => {
+                        self.base.write().unwrap().deposit(&cargo);
+                        for (index, amount) in cargo.iter().enumerate() {
+                            self.ledger.record(self.age, Some(*id), ResourceKind(index), Operation::Deposit, *amount as u64);
                         }
-                        EventType::Deposit((cristal, energy)) => {
-                            self.base.crystal += cristal;
-                            self.base.energy += energy;
+                        self.reservations.write().unwrap().remove(id);
+                        if let Some(robot) = self.robots.get_mut(id) {
+                            robot.cargo = 0;
                         }
-                        EventType::Extract(resource_id, (requested, rate)) => {
+                    }
+                    EventType::Extract(resource_id, (requested, rate), limit) => {
+                        let can_extract = self.robots.get(id).is_some_and(|robot| robot.battery >= EXTRACT_COST);
+                        if can_extract {
                             if let Some(resource) = self.resources.write().unwrap().get_mut(&resource_id) {
-                                let extracted = resource.gather(requested, rate);
-                                if let Some(sender) = self.senders.get(&id) {
+                                let extracted = resource.gather(requested, rate, limit);
+                                self.ledger.record(self.age, Some(*id), extracted.0, Operation::Extraction, extracted.1 as u64);
+                                if let Some(robot) = self.robots.get_mut(id) {
+                                    robot.cargo += extracted.1;
+                                    robot.battery -= EXTRACT_COST;
+                                }
+                                if let Some(sender) = self.senders.get(id) {
                                     let _ = sender.send(EventType::Collect(extracted));
                                 }
                             }
                         }
-                        EventType::Tick | EventType::Collect((_, _)) | EventType::Nothing => {
-                        }
+                    }
+                    EventType::Tick | EventType::Collect((_, _)) | EventType::MoveRefused(_) | EventType::Yield | EventType::Battery(_) | EventType::ResourceDepleted(_) | EventType::ResourceDiscovered(_) | EventType::Nothing => {
                     }
                 }
             }
-            self.clear_empty_resources();
-            self.decay_passage_counters();
-            self.update_explore_matrix();
+        }
+        moves
+    }
+
+    fn resolve_moves(&mut self, mut moves: Vec<(u32, Localization)>) -> Vec<(u32, Localization)> {
+        let map_matrix = self.map_matrix.read().unwrap();
+        let mut occupancy: HashMap<(u32, u32), usize> = HashMap::new();
+        for robot in self.robots.values() {
+            *occupancy.entry((robot.loc.x, robot.loc.y)).or_insert(0) += 1;
+        }
+
+        let mut progress = true;
+        while progress {
+            progress = false;
+            moves.retain(|&(id, new_loc)| {
+                if let Some(robot) = self.robots.get_mut(&id) {
+                    if robot.loc.same_loc(&new_loc) {
+                        return false;
+                    }
+                    if robot.battery < MOVE_COST {
+                        return true;
+                    }
+                    let is_shared = self.base_stacking
+                        && map_matrix[new_loc.x as usize][new_loc.y as usize].display == self.display_base;
+                    if !is_shared && occupancy.get(&(new_loc.x, new_loc.y)).is_some_and(|&count| count > 0) {
+                        return true;
+                    }
+                    if let Some(count) = occupancy.get_mut(&(robot.loc.x, robot.loc.y)) {
+                        *count -= 1;
+                    }
+                    *occupancy.entry((new_loc.x, new_loc.y)).or_insert(0) += 1;
+                    robot.loc = new_loc;
+                    robot.battery -= MOVE_COST;
+                    progress = true;
+                }
+                false
+            });
+        }
+
+        for (id, _) in moves.iter() {
+            if let (Some(robot), Some(sender)) = (self.robots.get(id), self.senders.get(id)) {
+                let _ = sender.send(EventType::MoveRefused(robot.loc));
+            }
+        }
+        moves
+    }
+
+    fn recharge_robots(&mut self) {
+        let map_matrix = self.map_matrix.read().unwrap();
+        let mut base = self.base.write().unwrap();
+        for (id, robot) in self.robots.iter_mut() {
+            let on_base = map_matrix[robot.loc.x as usize][robot.loc.y as usize].display == self.display_base;
+            if let (true, Some(fuel)) = (on_base, base.fuel) {
+                let available = base.amount(fuel).saturating_mul(BATTERY_PER_ENERGY as u64);
+                let amount = RECHARGE_RATE
+                    .min(self.battery_capacity.saturating_sub(robot.battery))
+                    .min(available.min(u16::MAX as u64) as u16);
+                let consumed = amount.div_ceil(BATTERY_PER_ENERGY);
+                robot.battery += amount;
+                base.pay(&[(fuel, consumed)]);
+                self.ledger.record(self.age, Some(*id), fuel, Operation::Transfer, consumed as u64);
+            }
+            robot.disabled = robot.battery == 0 && !on_base;
+            robot.idle = on_base && base.waits_for_fuel(robot.battery, self.battery_capacity);
+            if !robot.disabled {
+                if let Some(sender) = self.senders.get(id) {
+                    let _ = sender.send(EventType::Battery(robot.battery));
+                }
+            }
+        }
+    }
+
+    pub fn order_robot(&mut self, nature: Nature) -> Result<(), &'static str> {
+        if self.build_queue.len() >= MAX_QUEUE {
+            return Err("Build queue is full.");
+        }
+        self.build_queue.push_back(BuildOrder::new(nature));
+        Ok(())
+    }
+
+    fn run_controller(&mut self) {
+        if let Some(policy) = self.controller {
+            if let Some(nature) = policy.decide(&self.observe(), &self.registry) {
+                let _ = self.order_robot(nature);
+            }
+        }
+    }
+
+    pub fn observe(&self) -> Observation {
+        let base = self.base.read().unwrap().clone();
+        let resources = self.resources.read().unwrap();
+        let known_resources = self.finded_resources.read().unwrap().iter()
+            .filter(|id| resources.get(id).is_some_and(|resource| resource.remaining_quantity > 0))
+            .count();
+        let scouts = self.robots.values()
+            .filter(|robot| matches!(robot.nature, Nature::Scout) && !robot.disabled)
+            .count();
+        let gatherers = self.robots.values()
+            .filter(|robot| matches!(robot.nature, Nature::Gatherer) && !robot.disabled)
+            .count();
+        Observation {
+            base,
+            explored: self.explored_fraction(),
+            known_resources,
+            scouts,
+            gatherers,
+            queued: self.build_queue.len(),
+        }
+    }
+
+    pub fn explored_fraction(&self) -> f32 {
+        let map_matrix = self.map_matrix.read().unwrap();
+        let mut walkable = 0;
+        let mut explored = 0;
+        for row in map_matrix.iter() {
+            for cell in row.iter() {
+                if cell.display != self.display_obstacle {
+                    walkable += 1;
+                    if cell.explore != -1 {
+                        explored += 1;
+                    }
+                }
+            }
+        }
+        if walkable == 0 {
+            return 0.0;
+        }
+        explored as f32 / walkable as f32
+    }
+
+    fn advance_production(&mut self) {
+        let Some(order) = self.build_queue.front_mut() else {
+            return;
+        };
+        if !order.started {
+            let cost = self.registry.resolve(Recipe::for_nature(order.nature).cost);
+            let mut base = self.base.write().unwrap();
+            if !base.can_afford(&cost) {
+                return;
+            }
+            base.pay(&cost);
+            for &(kind, amount) in cost.iter() {
+                self.ledger.record(self.age, None, kind, Operation::Spend, amount as u64);
+            }
+            order.started = true;
+            return;
+        }
+        order.remaining = order.remaining.saturating_sub(1);
+        if order.remaining > 0 {
+            return;
+        }
+        let nature = order.nature;
+        if let Some(loc) = self.free_spawn(&[]) {
+            self.build_queue.pop_front();
+            match nature {
+                Nature::Scout => self.add_scout(loc.x, loc.y),
+                Nature::Gatherer => self.add_gatherer(loc.x, loc.y),
+            }
+        }
+    }
+
+    fn free_spawn(&self, preferred: &[Localization]) -> Option<Localization> {
+        let map_matrix = self.map_matrix.read().unwrap();
+        let mut candidates = preferred.to_vec();
+        candidates.push(self.base.read().unwrap().loc);
+        for x in 0..self.rows {
+            for y in 0..self.cols {
+                if map_matrix[x as usize][y as usize].display == self.display_base {
+                    candidates.push(Localization { x, y });
+                }
+            }
+        }
+        candidates.into_iter().find(|loc| {
+            let shared = self.base_stacking && map_matrix[loc.x as usize][loc.y as usize].display == self.display_base;
+            shared || !self.robots.values().any(|robot| robot.loc.same_loc(loc))
+        })
+    }
+
+    pub fn purchase_upgrade(&mut self, kind: UpgradeKind) -> Result<u8, &'static str> {
+        let mut base = self.base.write().unwrap();
+        let mut upgrades = self.upgrades.write().unwrap();
+        let cost = upgrades.cost(kind, &self.registry).unwrap_or_default();
+        let level = upgrades.purchase(kind, &mut base, &self.registry)?;
+        for (resource_kind, amount) in cost {
+            self.ledger.record(self.age, None, resource_kind, Operation::Spend, amount as u64);
+        }
+        Ok(level)
+    }
+
+    pub fn stock(&self) -> HashMap<String, u64> {
+        self.registry.named(&self.base.read().unwrap().stock)
+    }
+
+    pub fn stock_of(&self, name: &str) -> u64 {
+        self.registry.kind(name).map_or(0, |kind| self.base.read().unwrap().amount(kind))
+    }
+
+    pub fn build_states(&self) -> Vec<BuildState> {
+        self.build_queue.iter().map(|order| order.state()).collect()
+    }
+
+    pub fn robot_states(&self) -> Vec<RobotState> {
+        let mut states: Vec<RobotState> = self.robots.iter()
+            .map(|(id, robot)| RobotState {
+                id: *id,
+                kind: robot.nature.name(),
+                x: robot.loc.x,
+                y: robot.loc.y,
+                battery: robot.battery,
+                disabled: robot.disabled,
+                idle: robot.idle,
+            })
+            .collect();
+        states.sort_by_key(|state| state.id);
+        states
+    }
+
+    fn detect_deadlocks(&mut self, refused: &[(u32, Localization)]) {
+        for (id, robot) in self.robots.iter_mut() {
+            if refused.iter().any(|(refused_id, _)| refused_id == id) {
+                robot.waiting += 1;
+            } else {
+                robot.waiting = 0;
+            }
+        }
+
+        let mut waits_for: HashMap<u32, u32> = HashMap::new();
+        for &(id, wanted) in refused {
+            if let Some((&other, _)) = self.robots.iter().find(|&(&other, robot)| other != id && robot.loc.same_loc(&wanted)) {
+                waits_for.insert(id, other);
+            }
+        }
+
+        let mut yielders: HashSet<u32> = HashSet::new();
+        for &start in waits_for.keys() {
+            let mut chain = vec![start];
+            let mut current = start;
+            while let Some(&next) = waits_for.get(&current) {
+                if next == start {
+                    if let Some(&lowest) = chain.iter().min_by_key(|&&id| self.robots[&id].priority(id)) {
+                        yielders.insert(lowest);
+                    }
+                    break;
+                }
+                if chain.contains(&next) {
+                    break;
+                }
+                chain.push(next);
+                current = next;
+            }
+        }
+        for (id, robot) in self.robots.iter() {
+            if robot.waiting >= DEADLOCK_AFTER {
+                yielders.insert(*id);
+            }
+        }
+
+        let mut planner = self.planner.write().unwrap();
+        for id in yielders {
+            planner.release(id);
+            if let Some(robot) = self.robots.get_mut(&id) {
+                robot.waiting = 0;
+            }
+            if let Some(sender) = self.senders.get(&id) {
+                let _ = sender.send(EventType::Yield);
+            }
+        }
+    }
+
+    fn notify_gatherers(&self, event: EventType) {
+        for (id, robot) in self.robots.iter() {
+            if let Nature::Gatherer = robot.nature {
+                if let Some(sender) = self.senders.get(id) {
+                    let _ = sender.send(event.clone());
+                }
+            }
         }
     }
 
@@ -336,20 +1075,17 @@ impl Game {
 
         let resources = self.resources.read().unwrap();
 
-        for (_, resource) in resources.iter() {
+        for resource in resources.values() {
             let x = resource.loc.x as usize;
             let y = resource.loc.y as usize;
             if map_matrix[x][y].explore != -1 {
-                result_map[x][y].display = match resource.kind {
-                    ResourceKind::Crystal => 'C',
-                    ResourceKind::Energy => 'E',
-                };
+                result_map[x][y].display = resource.display;
             } else {
                 result_map[x][y].display = self.display_void;
             }
         }
 
-        for (_, robot) in &self.robots {
+        for robot in self.robots.values() {
             let x = robot.loc.x as usize;
             let y = robot.loc.y as usize;
             match robot.nature {
@@ -361,17 +1097,41 @@ impl Game {
         result_map
     }
 
-    pub fn generate_resources(&mut self, id_generator: &mut IDGenerator, number: u8) {
-        for i in 0..number {
-            if i % 2 == 0 {
-                self.add_resource("crystal", 40, id_generator);
-            } else {
-                self.add_resource("energy", 40, id_generator);
+    pub fn generate_resources(&mut self, number: u8) -> Result<(), PlacementError> {
+        let counted: Vec<Option<u8>> = (0..self.registry.len())
+            .map(|index| self.resource_rules.get(index).and_then(|rule| rule.count))
+            .collect();
+        for (index, count) in counted.iter().enumerate() {
+            let name = self.registry.types[index].name.clone();
+            for _ in 0..count.unwrap_or(0) {
+                self.add_resource(&name)?;
+            }
+        }
+
+        let weights: Vec<i64> = self.registry.types.iter()
+            .zip(counted.iter())
+            .map(|(resource_type, count)| if count.is_some() { 0 } else { resource_type.spawn_weight as i64 })
+            .collect();
+        let total_weight: i64 = weights.iter().sum();
+        if total_weight == 0 {
+            return Ok(());
+        }
+        let mut current_weights = vec![0i64; self.registry.len()];
+        for _ in 0..number {
+            for (current, weight) in current_weights.iter_mut().zip(weights.iter()) {
+                *current += weight;
             }
+            let Some(index) = (0..current_weights.len()).max_by_key(|&index| (current_weights[index], std::cmp::Reverse(index))) else {
+                return Ok(());
+            };
+            current_weights[index] -= total_weight;
+            let name = self.registry.types[index].name.clone();
+            self.add_resource(&name)?;
         }
+        Ok(())
     }
 
-    fn clear_empty_resources(&mut self) {
+    fn clear_empty_resources(&mut self) -> Vec<u32> {
         let mut resources = self.resources.write().unwrap();
         let ids_to_remove: Vec<u32> = resources.iter()
             .filter_map(|(id, resource)| {
@@ -383,41 +1143,76 @@ impl Game {
             })
             .collect();
         let mut finded_resources = self.finded_resources.write().unwrap();
+        let mut reservations = self.reservations.write().unwrap();
         for id in ids_to_remove.iter() {
-            resources.remove(id);
+            if let Some(resource) = resources.remove(id) {
+                let respawn_after = self.resource_rules.get(resource.kind.0).and_then(|rule| rule.respawn_after);
+                if let Some(delay) = respawn_after {
+                    self.pending_respawns.push((self.age.saturating_add(delay), resource.kind, 0));
+                }
+            }
             finded_resources.retain(|&resource_id| resource_id != *id);
+            reservations.retain(|_, resource_id| resource_id != id);
+        }
+        ids_to_remove
+    }
+
+    fn regrow_resources(&mut self) {
+        let mut resources = self.resources.write().unwrap();
+        for resource in resources.values_mut() {
+            let regrow_every = self.resource_rules.get(resource.kind.0).and_then(|rule| rule.regrow_every);
+            if let Some(period) = regrow_every {
+                if period > 0 && self.age % period == 0 && resource.remaining_quantity < resource.initial_quantity {
+                    resource.remaining_quantity += 1;
+                }
+            }
+        }
+    }
+
+    fn respawn_resources(&mut self) {
+        let age = self.age;
+        let (due, pending): (Vec<_>, Vec<_>) = std::mem::take(&mut self.pending_respawns)
+            .into_iter()
+            .partition(|&(at, _, _)| at <= age);
+        self.pending_respawns = pending;
+        for (_, kind, failures) in due {
+            let name = self.registry.get(kind).name.clone();
+            if self.add_resource(&name).is_err() && failures + 1 < MAX_RESPAWN_FAILURES {
+                let delay = self.resource_rules.get(kind.0).and_then(|rule| rule.respawn_after).unwrap_or(1).max(1);
+                self.pending_respawns.push((age.saturating_add(delay.saturating_mul(2u32.pow(failures + 1))), kind, failures + 1));
+            }
         }
     }
 
     pub fn generate_map_obstacles(&mut self) {
-        let perlin = Perlin::new();
-        let scale = ((self.rows + self.cols) as f64) / 10.0;
+        let obstacles = self.generator.generator(self.terrain, self.topology).obstacles(self.seed, self.rows, self.cols);
+        let noise_map = self.terrain.noise_map(self.seed, self.rows, self.cols, self.topology);
     
         let mut map_matrix = self.map_matrix.write().unwrap();
-        let threshold = perlin.get([self.seed as f64 / 100.0, self.seed as f64 / 100.0]);
     
         for i in 0..self.rows {
             for j in 0..self.cols {
-                let noise_value = perlin.get([i as f64 / scale, j as f64 / scale]);
-                if noise_value > threshold + 0.2 {
+                if obstacles[i as usize][j as usize] {
                     map_matrix[i as usize][j as usize].display = self.display_obstacle;
                 }
             }
         }
     
-        let center_x = self.rows / 2;
-        let center_y = self.cols / 2;
+        let base_loc = self.base_placement.location(self.seed, self.rows, self.cols);
+        self.base.write().unwrap().loc = base_loc;
+        let center_x = base_loc.x;
+        let center_y = base_loc.y;
     
-        let safe_zone_size = 6;
+        let safe_zone_size = self.terrain.safe_zone_radius;
         let mut safe_zone_noise = vec![vec![false; self.cols as usize]; self.rows as usize];
 
         for i in 0..self.rows {
             for j in 0..self.cols {
-                let dist_x = (i as f64 - center_x as f64).abs();
-                let dist_y = (j as f64 - center_y as f64).abs();
+                let dist_x = self.topology.delta(i, center_x, self.rows) as f64;
+                let dist_y = self.topology.delta(j, center_y, self.cols) as f64;
                 let dist = (dist_x.powi(2) + dist_y.powi(2)).sqrt();
     
-                let safe_zone_threshold = safe_zone_size as f64 + perlin.get([i as f64 / scale, j as f64 / scale]) * 5.0;
+                let safe_zone_threshold = safe_zone_size as f64 + noise_map[i as usize][j as usize] * 5.0;
     
                 if dist < safe_zone_threshold {
                     safe_zone_noise[i as usize][j as usize] = true;
@@ -433,11 +1228,339 @@ impl Game {
         }
         for i in (center_x - 1) as i32..=(center_x + 1) as i32 {
             for j in (center_y - 1) as i32..=(center_y + 1) as i32 {
-                if i >= 0 && j >= 0 && i < self.rows as i32 && j < self.cols as i32 {
-                    map_matrix[i as usize][j as usize].display = self.display_base;
-                    map_matrix[i as usize][j as usize].explore = 30;
+                if let Some((x, y)) = self.topology.wrap(i, j, self.rows, self.cols) {
+                    map_matrix[x as usize][y as usize].display = self.display_base;
+                    map_matrix[x as usize][y as usize].explore = 30;
                 }
             }
         }
+        for spawn in self.spawn_points.iter() {
+            let cell = &mut map_matrix[spawn.x as usize][spawn.y as usize];
+            if cell.display == self.display_obstacle {
+                cell.display = self.display_void;
+            }
+        }
+        drop(map_matrix);
+        self.update_base_flow();
+        self.generated_reachable_fraction = Some(self.map_metrics().reachable_fraction);
+        self.ensure_reachability();
+        if self.spawn_points.is_empty() {
+            self.spawn_points.push(base_loc);
+        }
+    }
+
+    fn unreachable_cells(&self) -> Vec<Localization> {
+        let map_matrix = self.map_matrix.read().unwrap();
+        let base_flow = self.base_flow.read().unwrap();
+        let mut cells = Vec::new();
+        for x in 0..self.rows {
+            for y in 0..self.cols {
+                let loc = Localization { x, y };
+                if map_matrix[x as usize][y as usize].display != self.display_obstacle && base_flow.distance(loc).is_none() {
+                    cells.push(loc);
+                }
+            }
+        }
+        cells
+    }
+
+    pub fn ensure_reachability(&mut self) {
+        loop {
+            let unreachable = self.unreachable_cells();
+            if unreachable.is_empty() {
+                return;
+            }
+            let mut map_matrix = self.map_matrix.write().unwrap();
+            match self.reachability {
+                Reachability::Fill => {
+                    for loc in unreachable {
+                        map_matrix[loc.x as usize][loc.y as usize].display = self.display_obstacle;
+                    }
+                    return;
+                }
+                Reachability::Carve => {
+                    let base_flow = self.base_flow.read().unwrap();
+                    let mut reachable = Vec::new();
+                    for x in 0..self.rows {
+                        for y in 0..self.cols {
+                            let loc = Localization { x, y };
+                            if base_flow.distance(loc).is_some() {
+                                reachable.push(loc);
+                            }
+                        }
+                    }
+                    drop(base_flow);
+                    let corridor_flow = FlowField::from_targets_where(&map_matrix, &reachable, self.topology, |_| true);
+                    let Some(start) = unreachable.into_iter().min_by_key(|loc| corridor_flow.distance(*loc)) else {
+                        return;
+                    };
+                    for loc in corridor_flow.path_from(start).unwrap_or_default() {
+                        let cell = &mut map_matrix[loc.x as usize][loc.y as usize];
+                        if cell.display == self.display_obstacle {
+                            cell.display = self.display_void;
+                        }
+                    }
+                }
+            }
+            drop(map_matrix);
+            self.update_base_flow();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn game(base_stacking: bool) -> Game {
+        let mut game = Game::new(15, 15, 1, ' ', '8', '#', 'S', 'G');
+        game.base_stacking = base_stacking;
+        let mut map_matrix = game.map_matrix.write().unwrap();
+        for x in 6..=8 {
+            for y in 6..=8 {
+                map_matrix[x][y].display = '#';
+            }
+        }
+        drop(map_matrix);
+        game
+    }
+
+    fn place(game: &mut Game, id: u32, x: u32, y: u32) {
+        game.robots.insert(id, Robot::new(Nature::Scout, Localization { x, y }, 100));
+    }
+
+    fn to(x: u32, y: u32) -> Localization {
+        Localization { x, y }
+    }
+
+    fn position(game: &Game, id: u32) -> (u32, u32) {
+        let loc = game.robots[&id].loc;
+        (loc.x, loc.y)
+    }
+
+    fn refused_ids(refused: &[(u32, Localization)]) -> Vec<u32> {
+        refused.iter().map(|(id, _)| *id).collect()
+    }
+
+    #[test]
+    fn first_robot_in_order_wins_a_contested_cell() {
+        let mut game = game(true);
+        place(&mut game, 1, 2, 2);
+        place(&mut game, 2, 2, 4);
+        let refused = game.resolve_moves(vec![(1, to(2, 3)), (2, to(2, 3))]);
+        assert_eq!(refused_ids(&refused), vec![2]);
+        assert_eq!(position(&game, 1), (2, 3));
+        assert_eq!(position(&game, 2), (2, 4));
+    }
+
+    #[test]
+    fn robots_can_follow_each_other_in_a_chain() {
+        let mut game = game(true);
+        place(&mut game, 1, 2, 2);
+        place(&mut game, 2, 2, 3);
+        let refused = game.resolve_moves(vec![(1, to(2, 3)), (2, to(2, 4))]);
+        assert!(refused.is_empty());
+        assert_eq!(position(&game, 1), (2, 3));
+        assert_eq!(position(&game, 2), (2, 4));
+        assert_eq!(game.robots[&1].battery, 100 - MOVE_COST);
+    }
+
+    #[test]
+    fn swaps_and_empty_batteries_are_refused() {
+        let mut game = game(true);
+        place(&mut game, 1, 2, 2);
+        place(&mut game, 2, 2, 3);
+        place(&mut game, 3, 4, 4);
+        game.robots.get_mut(&3).unwrap().battery = 0;
+        let refused = game.resolve_moves(vec![(1, to(2, 3)), (2, to(2, 2)), (3, to(4, 5))]);
+        assert_eq!(refused_ids(&refused), vec![1, 2, 3]);
+        assert_eq!(position(&game, 3), (4, 4));
+    }
+
+    #[test]
+    fn base_tiles_are_shared_only_with_base_stacking() {
+        for (base_stacking, expected) in [(true, vec![]), (false, vec![2])] {
+            let mut game = game(base_stacking);
+            place(&mut game, 1, 5, 6);
+            place(&mut game, 2, 6, 5);
+            let refused = game.resolve_moves(vec![(1, to(6, 6)), (2, to(6, 6))]);
+            assert_eq!(refused_ids(&refused), expected);
+        }
+    }
+
+    #[test]
+    fn robot_spawn_uses_distinct_cells_without_base_stacking() {
+        let mut game = game(false);
+        game.spawn_points = vec![to(1, 1)];
+        let mut used = Vec::new();
+        for index in 0..10 {
+            let loc = game.robot_spawn(index).unwrap();
+            place(&mut game, index as u32 + 1, loc.x, loc.y);
+            used.push((loc.x, loc.y));
+        }
+        assert_eq!(used[0], (1, 1));
+        assert_eq!(used[1], (7, 7));
+        used.sort();
+        used.dedup();
+        assert_eq!(used.len(), 10);
+        assert!(game.robot_spawn(10).is_none());
+
+        let mut stacked = self::game(true);
+        place(&mut stacked, 1, 7, 7);
+        assert_eq!(stacked.robot_spawn(0).map(|loc| (loc.x, loc.y)), Some((7, 7)));
+    }
+
+    fn listen(game: &mut Game, id: u32) -> Receiver<EventType> {
+        let (sender, receiver) = channel();
+        game.senders.insert(id, sender);
+        receiver
+    }
+
+    fn yielded(receiver: &Receiver<EventType>) -> bool {
+        receiver.try_iter().any(|event| matches!(event, EventType::Yield))
+    }
+
+    #[test]
+    fn lowest_priority_robot_yields_in_a_waiting_cycle() {
+        let mut game = game(true);
+        place(&mut game, 1, 2, 2);
+        place(&mut game, 2, 2, 3);
+        game.robots.get_mut(&2).unwrap().nature = Nature::Gatherer;
+        place(&mut game, 3, 10, 10);
+        let receivers: Vec<Receiver<EventType>> = (1..=3).map(|id| listen(&mut game, id)).collect();
+        game.detect_deadlocks(&[(1, to(2, 3)), (2, to(2, 2)), (3, to(10, 11))]);
+        assert!(yielded(&receivers[0]));
+        assert!(!yielded(&receivers[1]));
+        assert!(!yielded(&receivers[2]));
+        assert_eq!(game.robots[&1].waiting, 0);
+        assert_eq!(game.robots[&2].waiting, 1);
+        assert_eq!(game.robots[&3].waiting, 1);
+    }
+
+    #[test]
+    fn robots_waiting_too_long_yield() {
+        let mut game = game(true);
+        place(&mut game, 1, 2, 2);
+        place(&mut game, 2, 2, 3);
+        let receiver = listen(&mut game, 1);
+        for _ in 1..DEADLOCK_AFTER {
+            game.detect_deadlocks(&[(1, to(2, 3))]);
+            assert!(!yielded(&receiver));
+        }
+        assert_eq!(game.robots[&1].waiting, DEADLOCK_AFTER - 1);
+        game.detect_deadlocks(&[(2, to(2, 4))]);
+        assert_eq!(game.robots[&1].waiting, 0);
+        for _ in 0..DEADLOCK_AFTER {
+            game.detect_deadlocks(&[(1, to(2, 3))]);
+        }
+        assert!(yielded(&receiver));
+        assert_eq!(game.robots[&1].waiting, 0);
+    }
+
+    #[test]
+    fn robots_docked_without_fuel_are_idle_until_fuel_arrives() {
+        let mut game = game(true);
+        place(&mut game, 1, 7, 7);
+        place(&mut game, 2, 2, 2);
+        place(&mut game, 3, 6, 6);
+        game.robots.get_mut(&1).unwrap().battery = 30;
+        game.robots.get_mut(&2).unwrap().battery = 30;
+        game.recharge_robots();
+        let idle: Vec<bool> = game.robot_states().iter().map(|state| state.idle).collect();
+        assert_eq!(idle, vec![true, false, false]);
+
+        let fuel = game.base.read().unwrap().fuel.unwrap();
+        game.base.write().unwrap().stock[fuel.0] = 10;
+        game.recharge_robots();
+        assert!(!game.robots[&1].idle);
+        assert_eq!(game.robots[&1].battery, 30 + RECHARGE_RATE);
+    }
+
+    #[test]
+    fn orders_are_paid_when_affordable_and_spawn_after_their_build_time() {
+        let mut game = game(true);
+        let crystal = game.registry.kind("crystal").unwrap();
+        let energy = game.registry.kind("energy").unwrap();
+        game.base.write().unwrap().stock[energy.0] = 10;
+        game.order_robot(Nature::Scout).unwrap();
+        for _ in 0..3 {
+            game.advance_production();
+        }
+        assert!(!game.build_queue[0].started);
+        assert_eq!(game.base.read().unwrap().amount(energy), 10);
+        assert_eq!(game.ledger.totals(&game.registry)["crystal"].spent, 0);
+
+        game.base.write().unwrap().stock[crystal.0] = 25;
+        game.advance_production();
+        assert!(game.build_queue[0].started);
+        assert_eq!(game.base.read().unwrap().amount(crystal), 5);
+        assert_eq!(game.base.read().unwrap().amount(energy), 0);
+        let build_time = Recipe::for_nature(Nature::Scout).build_time;
+        for _ in 1..build_time {
+            game.advance_production();
+            assert!(game.robots.is_empty());
+        }
+        game.advance_production();
+        assert_eq!(game.robots.len(), 1);
+        assert!(game.build_queue.is_empty());
+    }
+
+    fn generated(generator: GeneratorKind, reachability: Reachability, seed: u64) -> Game {
+        let mut game = Game::new(30, 40, seed, ' ', '8', '#', 'S', 'G');
+        game.generator = generator;
+        game.reachability = reachability;
+        game.generate_map_obstacles();
+        game
+    }
+
+    fn obstacle_count(game: &Game) -> usize {
+        game.map_matrix.read().unwrap().iter().flatten().filter(|cell| cell.display == '8').count()
+    }
+
+    #[test]
+    fn carve_and_fill_leave_no_free_cell_unreachable() {
+        let mut repaired = 0;
+        for generator in [GeneratorKind::Perlin, GeneratorKind::Caves, GeneratorKind::Rocks] {
+            for seed in 0..10 {
+                let carved = generated(generator, Reachability::Carve, seed);
+                let filled = generated(generator, Reachability::Fill, seed);
+                assert!(carved.unreachable_cells().is_empty(), "{:?} carve seed {}", generator, seed);
+                assert!(filled.unreachable_cells().is_empty(), "{:?} fill seed {}", generator, seed);
+                if obstacle_count(&carved) < obstacle_count(&filled) {
+                    repaired += 1;
+                }
+            }
+        }
+        assert!(repaired > 0);
+    }
+
+    #[test]
+    fn over_full_resource_layouts_report_no_free_cell() {
+        let mut game = generated(GeneratorKind::Open, Reachability::Carve, 1);
+        let rules = HashMap::from([("crystal".to_string(), ResourceRule { count: Some(3), spacing: 100, ..ResourceRule::default() })]);
+        game.set_resource_rules(rules);
+        assert_eq!(game.generate_resources(0), Err(PlacementError::NoFreeCell("crystal".to_string())));
+        assert_eq!(game.resources.read().unwrap().len(), 1);
+
+        let mut game = generated(GeneratorKind::Open, Reachability::Carve, 1);
+        let rules = HashMap::from([("energy".to_string(), ResourceRule { min_base_distance: Some(1000), ..ResourceRule::default() })]);
+        game.set_resource_rules(rules);
+        assert_eq!(game.add_resource("energy"), Err(PlacementError::NoFreeCell("energy".to_string())));
+        assert_eq!(game.add_resource("ore"), Err(PlacementError::UnknownKind("ore".to_string())));
+    }
+
+    #[test]
+    fn reachable_fraction_is_measured_before_the_repair() {
+        let mut partial = 0;
+        for seed in 0..10 {
+            let game = generated(GeneratorKind::Caves, Reachability::Carve, seed);
+            let fraction = game.map_metrics().reachable_fraction;
+            assert!(fraction > 0.0 && fraction <= 1.0);
+            assert!(game.unreachable_cells().is_empty());
+            if fraction < 1.0 {
+                partial += 1;
+            }
+        }
+        assert!(partial > 0);
     }
 }
@@ -1,39 +1,68 @@
 use rand::prelude::*;
-use std::collections::{VecDeque, HashSet, HashMap};
+use std::collections::HashMap;
 use std::sync::{Arc, RwLock};
 use std::sync::mpsc::{Sender, Receiver};
 use crate::id_generator::IDGenerator;
 use crate::events::*;
 use crate::resources::*;
-use crate::game::{Localization, Cell};
+use crate::game::{Localization, Cell, Base, EXTRACT_COST, MOVE_COST, RECHARGE_MARGIN};
+use crate::flow_field::FlowField;
+use crate::topology::Topology;
+use crate::planner::{SpaceTimeTable, WINDOW};
+use crate::upgrades::{Upgrades, EXTRACT_AMOUNT};
+
+const NEEDED_KIND_BONUS: f32 = 1.5;
+const CLAIMED_PENALTY: f32 = 0.1;
+const REROUTE_AFTER: u8 = 3;
 
 pub struct Gatherer {
     pub id: u32,
     pub loc: Localization,
-    pub display: char,
     pub target: Option<u32>,
-    pub inventory: (u16, u16),
+    pub inventory: Cargo,
     pub inventory_size: u16,
+    pub cargo_limits: Vec<Option<u16>>,
+    pub delivering: bool,
+    pub extraction_rate: f32,
     pub path: Option<Vec<Localization>>,
+    pub discovered: Vec<u32>,
+    pub blocked: Option<Localization>,
+    pub blocked_ticks: u8,
+    pub replan_at: u32,
+    pub battery: u16,
+    pub battery_capacity: u16,
+    pub recharging: bool,
+    pub topology: Topology,
 }
 
 impl Gatherer {
     pub fn new(
         loc: Localization,
+        battery_capacity: u16,
+        cargo_limits: Vec<Option<u16>>,
+        topology: Topology,
         id_generator: &mut IDGenerator,
     ) -> Option<Self> {
         let id = id_generator.generate_id();
-        let display = 'G';
-
         Some(
             Self {
                 id,
                 loc,
                 target: None,
-                display,
-                inventory: (0, 0),
+                inventory: vec![0; cargo_limits.len()],
                 inventory_size: 10,
+                cargo_limits,
+                delivering: false,
+                extraction_rate: 1.0,
                 path: Some(Vec::new()),
+                discovered: Vec::new(),
+                blocked: None,
+                blocked_ticks: 0,
+                replan_at: 0,
+                battery: battery_capacity,
+                battery_capacity,
+                recharging: false,
+                topology,
             }
         )
     }
@@ -44,88 +73,150 @@ impl Gatherer {
         ))
     }
 
+    #[allow(clippy::too_many_arguments)]
     pub fn handle_events(
         &mut self,
         map_matrix: Arc<RwLock<Vec<Vec<Cell>>>>,
         resources: Arc<RwLock<HashMap<u32, Resource>>>,
-        base_loc: Localization,
+        base_flow: Arc<RwLock<FlowField>>,
+        base: Arc<RwLock<Base>>,
         seed: u64,
         finded_resources:  Arc<RwLock<Vec<u32>>>,
+        reservations: Arc<RwLock<HashMap<u32, u32>>>,
+        planner: Arc<RwLock<SpaceTimeTable>>,
+        upgrades: Arc<RwLock<Upgrades>>,
         gatherer_receiver: Receiver<EventType>,
         map_sender: Sender<EventType>,
         display_obstacle: char,
     ) {
 
-        loop {
-            if let Ok(event) = gatherer_receiver.recv() {
-                match event {
-                    EventType::Tick => {
-                        
-                        let map_matrix = map_matrix.read().unwrap();
-                        let resources = resources.read().unwrap();
-                        let finded_resources = finded_resources.read().unwrap();
-
-                        let map_matrix_copy = map_matrix.clone();
-                        let mut resources_copy = resources.clone();
-                        let finded_resources_copy = finded_resources.clone();
-                        let event = self.choose(&finded_resources_copy, &mut resources_copy, seed, &map_matrix_copy, base_loc, display_obstacle);
-                        let _ = map_sender.send(event);
+        while let Ok(event) = gatherer_receiver.recv() {
+            match event {
+                EventType::Tick => {
+                    
+                    let upgrades = *upgrades.read().unwrap();
+                    self.inventory_size = upgrades.cargo_capacity();
+                    self.extraction_rate = upgrades.extraction_rate();
+
+                    let map_matrix = map_matrix.read().unwrap();
+                    let resources = resources.read().unwrap();
+                    let finded_resources = finded_resources.read().unwrap();
+                    let base_flow = base_flow.read().unwrap();
+                    let base = base.read().unwrap().clone();
+                    let mut reservations = reservations.write().unwrap();
+                    let mut planner = planner.write().unwrap();
+
+                    let mut map_matrix_copy = map_matrix.clone();
+                    let mut resources_copy = resources.clone();
+                    let finded_resources_copy = finded_resources.clone();
+                    let detour = self.take_detour();
+                    if let Some(blocked) = detour {
+                        map_matrix_copy[blocked.x as usize][blocked.y as usize].display = display_obstacle;
+                    }
+                    self.replan(&resources_copy, &mut reservations, &map_matrix_copy, &base, detour, display_obstacle);
+                    if planner.take_invalidated(self.id) || planner.now >= self.replan_at {
+                        self.refresh_plan(&mut planner, &map_matrix_copy, &base_flow, display_obstacle);
+                    }
+                    let event = self.choose(&finded_resources_copy, &mut resources_copy, &mut reservations, &mut planner, seed, &map_matrix_copy, &base_flow, &base, display_obstacle);
+                    if self.path.as_ref().map_or(true, |path| path.is_empty()) {
+                        let now = planner.now;
+                        planner.hold(self.id, self.loc, now + 1, &map_matrix_copy);
+                        planner.hold(self.id, self.loc, now + 2, &map_matrix_copy);
                     }
-                    EventType::Collect(recolted) => {
-                        self.inventory.0 += recolted.0;
-                        self.inventory.1 += recolted.1;
+                    let _ = map_sender.send(event);
+                }
+                EventType::Collect((kind, amount)) => {
+                    self.inventory[kind.0] += amount;
+                }
+                EventType::MoveRefused(loc) => {
+                    if let Some(path) = self.path.as_mut() {
+                        path.insert(0, self.loc);
                     }
-                    _ => {
+                    self.blocked = Some(self.loc);
+                    self.blocked_ticks += 1;
+                    self.loc = loc;
+                    self.replan_at = 0;
+                }
+                EventType::Yield => {
+                    self.blocked_ticks = REROUTE_AFTER;
+                }
+                EventType::Battery(level) => {
+                    self.battery = level;
+                }
+                EventType::ResourceDepleted(resource_id) if self.target == Some(resource_id) => {
+                    self.target = None;
+                    if !self.is_full() && !self.delivering {
+                        self.path = Some(Vec::new());
                     }
                 }
+                EventType::ResourceDiscovered(resource_id) => {
+                    self.discovered.push(resource_id);
+                }
+                _ => {
+                }
             }
         }
     }
 
+    #[allow(clippy::too_many_arguments)]
     pub fn choose(
         &mut self,
-        finded_resources: &Vec<u32>,
+        finded_resources: &[u32],
         resources: &mut HashMap<u32, Resource>,
+        reservations: &mut HashMap<u32, u32>,
+        planner: &mut SpaceTimeTable,
         seed: u64,
-        map_matrix: &Vec<Vec<Cell>>,
-        base_loc: Localization,
+        map_matrix: &[Vec<Cell>],
+        base_flow: &FlowField,
+        base: &Base,
         display_obstacle: char,
     ) -> EventType {
+        if let Some(event) = self.recharge(finded_resources, resources, reservations, planner, map_matrix, base_flow, base, display_obstacle) {
+            return event;
+        }
     
         if self.path.as_ref().map_or(true, |p| p.is_empty()) {
             // Si la capacité de l'inventaire est pleine, se rendre à la base.
-            if self.inventory.0 + self.inventory.1 >= self.inventory_size {
-                self.seek(map_matrix, base_loc, display_obstacle);
-                if base_loc.same_loc(&self.loc) {
-                    let deposit = (self.inventory.0, self.inventory.1);
-                    self.inventory = (0, 0);
+            if self.is_full() || self.delivering {
+                self.plan_to(planner, map_matrix, base_flow, planner.now + 1, display_obstacle);
+                if base_flow.distance(self.loc) == Some(0) {
+                    let deposit = self.take_inventory();
+                    self.target = None;
                     return EventType::Deposit(deposit);
                 }
             } else {
                 if self.target.is_none() {
-                    self.find(finded_resources, resources, seed);
+                    self.find(finded_resources, resources, reservations, base, map_matrix, seed, display_obstacle);
                     if let Some(target_id) = self.target {
                         if let Some(resource) = resources.get(&target_id) {
-                            self.seek(map_matrix, resource.loc, display_obstacle);
+                            self.seek(planner, map_matrix, resource.loc, display_obstacle);
                             return EventType::Nothing;
                         }
+                    } else if self.load() > 0 {
+                        self.delivering = true;
                     }
                     return EventType::Nothing;
                 } else {
                     if let Some(target_id) = self.target {
                         if let Some(resource) = resources.get_mut(&target_id) {
                             if self.loc.same_loc(&resource.loc) {
-                                if resource.remaining_quantity == 0 {
+                                let space = self.space_for(resource.kind);
+                                if resource.remaining_quantity == 0 || space == 0 {
                                     self.target = None;
-                                    self.find(finded_resources, resources, seed);
+                                    reservations.remove(&self.id);
+                                }
+                                if space == 0 {
+                                    self.delivering = true;
+                                    return EventType::Nothing;
                                 }
-                                return EventType::Extract(target_id,(10, 1.0));
+                                return EventType::Extract(target_id, (EXTRACT_AMOUNT, self.extraction_rate), space);
                             } else {
-                                self.seek(map_matrix, resource.loc, display_obstacle);
+                                self.seek(planner, map_matrix, resource.loc, display_obstacle);
                                 return EventType::Nothing;
                             }
                         } else {
                             self.target = None;
+                            reservations.remove(&self.id);
                             return EventType::Nothing;
                         }
                     }
@@ -135,84 +226,266 @@ impl Gatherer {
             self.step();
             return EventType::Moved(self.loc);
         }
-        return EventType::Nothing;
+        EventType::Nothing
     }
 
+    #[allow(clippy::too_many_arguments)]
     fn find(
         &mut self,
-        finded_resources: &Vec<u32>,
+        finded_resources: &[u32],
         resources: &HashMap<u32, Resource>,
-        seed: u64
+        reservations: &mut HashMap<u32, u32>,
+        base: &Base,
+        map_matrix: &[Vec<Cell>],
+        seed: u64,
+        display_obstacle: char,
     ) {
         let mut rng = self.initialize_rng(seed);
+        let distances = FlowField::new(map_matrix, self.loc, display_obstacle, self.topology);
+        let needed_kind = base.most_needed_kind();
 
-        if let Some(&target_id) = finded_resources.choose(&mut rng) {
+        let mut best_score = 0.0;
+        let mut best_targets: Vec<u32> = Vec::new();
+        for &target_id in finded_resources {
             if let Some(resource) = resources.get(&target_id) {
-                if resource.remaining_quantity > 0 {
-                    self.target = Some(target_id);
+                if resource.remaining_quantity == 0 || self.space_for(resource.kind) == 0 {
+                    continue;
+                }
+                if let Some(distance) = distances.distance(resource.loc) {
+                    let score = self.score(target_id, resource, distance, reservations, needed_kind);
+                    if score > best_score {
+                        best_score = score;
+                        best_targets.clear();
+                    }
+                    if score == best_score {
+                        best_targets.push(target_id);
+                    }
                 }
             }
         }
+
+        reservations.remove(&self.id);
+        if let Some(&target_id) = best_targets.choose(&mut rng) {
+            self.target = Some(target_id);
+            reservations.insert(self.id, target_id);
+        }
     }
 
-    fn seek(&mut self, map_matrix: &Vec<Vec<Cell>>, target: Localization, display_obstacle: char) {
-        let start = self.loc;
+    fn score(
+        &self,
+        target_id: u32,
+        resource: &Resource,
+        distance: u32,
+        reservations: &HashMap<u32, u32>,
+        needed_kind: ResourceKind,
+    ) -> f32 {
+        let claims = reservations.iter()
+            .filter(|&(&gatherer_id, &resource_id)| gatherer_id != self.id && resource_id == target_id)
+            .count() as u16;
+        let available = resource.remaining_quantity.saturating_sub(claims.saturating_mul(self.inventory_size));
 
-        let rows = map_matrix.len();
-        let cols = map_matrix[0].len();
+        let space = self.space_for(resource.kind);
+        let mut score = if available > 0 {
+            available.min(space) as f32
+        } else {
+            resource.remaining_quantity.min(space) as f32 * CLAIMED_PENALTY
+        };
+        if resource.kind == needed_kind {
+            score *= NEEDED_KIND_BONUS;
+        }
+        score / (distance + 1) as f32
+    }
 
-        let directions = vec![
-            (-1, 0),
-            (1, 0),
-            (0, -1),
-            (0, 1),
-        ];
+    fn seek(&mut self, planner: &mut SpaceTimeTable, map_matrix: &[Vec<Cell>], target: Localization, display_obstacle: char) {
+        let heuristic = FlowField::new(map_matrix, target, display_obstacle, self.topology);
+        self.plan_to(planner, map_matrix, &heuristic, planner.now + 1, display_obstacle);
+    }
 
-        let mut queue: VecDeque<(u32, u32)> = VecDeque::new();
-        queue.push_back((start.x, start.y));
+    fn plan_to(
+        &mut self,
+        planner: &mut SpaceTimeTable,
+        map_matrix: &[Vec<Cell>],
+        heuristic: &FlowField,
+        depart: u32,
+        display_obstacle: char,
+    ) {
+        let path = planner.plan(self.id, self.loc, depart, map_matrix, heuristic, display_obstacle)
+            .or_else(|| heuristic.path_from(self.loc));
+        self.path = Some(path.unwrap_or_default());
+        self.replan_at = depart + WINDOW / 2;
+    }
 
-        let mut visited: HashSet<(u32, u32)> = HashSet::new();
-        visited.insert((start.x, start.y));
+    fn refresh_plan(
+        &mut self,
+        planner: &mut SpaceTimeTable,
+        map_matrix: &[Vec<Cell>],
+        base_flow: &FlowField,
+        display_obstacle: char,
+    ) {
+        let destination = self.path.as_ref().and_then(|path| path.last().copied());
+        if let Some(destination) = destination {
+            let depart = planner.now;
+            if base_flow.distance(destination) == Some(0) {
+                self.plan_to(planner, map_matrix, base_flow, depart, display_obstacle);
+            } else {
+                let heuristic = FlowField::new(map_matrix, destination, display_obstacle, self.topology);
+                self.plan_to(planner, map_matrix, &heuristic, depart, display_obstacle);
+            }
+        }
+    }
 
-        let mut parent_map: HashMap<(u32, u32), (u32, u32)> = HashMap::new();
+    fn replan(
+        &mut self,
+        resources: &HashMap<u32, Resource>,
+        reservations: &mut HashMap<u32, u32>,
+        map_matrix: &[Vec<Cell>],
+        base: &Base,
+        detour: Option<Localization>,
+        display_obstacle: char,
+    ) {
+        let discovered = std::mem::take(&mut self.discovered);
 
-        while let Some((x, y)) = queue.pop_front() {
-            if (x, y) == (target.x, target.y) {
-                let mut path = Vec::new();
-                let mut current = (x, y);
+        if let (Some(path), Some(blocked)) = (&self.path, detour) {
+            if path.iter().any(|loc| loc.same_loc(&blocked)) {
+                self.path = Some(Vec::new());
+                return;
+            }
+        }
 
-                while let Some(&parent) = parent_map.get(&current) {
-                    if parent != (start.x, start.y) {
-                        let loc = Localization { x: parent.0, y: parent.1 };
-                        path.push(loc);
-                    }
-                    current = parent;
+        if self.is_full() || self.delivering || discovered.is_empty() {
+            return;
+        }
+        if let Some(target_id) = self.target {
+            if let Some(target) = resources.get(&target_id) {
+                let needed_kind = base.most_needed_kind();
+                let remaining = self.path.as_ref().map_or(0, |path| path.len()) as u32;
+                let current_score = self.score(target_id, target, remaining, reservations, needed_kind);
+                let distances = FlowField::new(map_matrix, self.loc, display_obstacle, self.topology);
+
+                let has_better = discovered.iter().any(|&resource_id| {
+                    resources.get(&resource_id).is_some_and(|resource| {
+                        resource.remaining_quantity > 0 && distances.distance(resource.loc).is_some_and(|distance| {
+                            self.score(resource_id, resource, distance, reservations, needed_kind) > current_score
+                        })
+                    })
+                });
+                if has_better {
+                    self.target = None;
+                    self.path = Some(Vec::new());
+                    reservations.remove(&self.id);
                 }
+            }
+        }
+    }
 
-                path.reverse();
-                path.push(Localization { x: target.x, y: target.y });
-                self.path = Some(path);
+    fn take_detour(&mut self) -> Option<Localization> {
+        let blocked = self.blocked.take();
+        if blocked.is_none() {
+            self.blocked_ticks = 0;
+        }
+        if self.blocked_ticks < REROUTE_AFTER {
+            return None;
+        }
+        self.blocked_ticks = 0;
+        let blocked = blocked?;
+        let destination = self.path.as_ref().and_then(|path| path.last().copied());
+        if destination.is_some_and(|loc| loc.same_loc(&blocked)) {
+            return None;
+        }
+        self.path = Some(Vec::new());
+        Some(blocked)
+    }
 
-                return;
+    #[allow(clippy::too_many_arguments)]
+    fn recharge(
+        &mut self,
+        finded_resources: &[u32],
+        resources: &HashMap<u32, Resource>,
+        reservations: &mut HashMap<u32, u32>,
+        planner: &mut SpaceTimeTable,
+        map_matrix: &[Vec<Cell>],
+        base_flow: &FlowField,
+        base: &Base,
+        display_obstacle: char,
+    ) -> Option<EventType> {
+        if !self.recharging {
+            let distance = base_flow.distance(self.loc)?;
+            if distance == 0 || self.battery > (distance as u16).saturating_mul(MOVE_COST).saturating_add(RECHARGE_MARGIN) {
+                return None;
+            }
+            self.recharging = true;
+            self.plan_to(planner, map_matrix, base_flow, planner.now + 1, display_obstacle);
+            return Some(EventType::Nothing);
+        }
+
+        if base_flow.distance(self.loc) != Some(0) {
+            if self.path.as_ref().map_or(true, |path| path.is_empty()) {
+                self.plan_to(planner, map_matrix, base_flow, planner.now + 1, display_obstacle);
+                return Some(EventType::Nothing);
             }
+            return None;
+        }
+        if self.load() > 0 {
+            let deposit = self.take_inventory();
+            self.target = None;
+            return Some(EventType::Deposit(deposit));
+        }
+        if base.fuel_stock() == 0 && self.battery < self.battery_capacity {
+            if let Some(resource) = self.fuel_run(finded_resources, resources, base_flow, base) {
+                self.recharging = false;
+                self.target = Some(resource.id);
+                reservations.insert(self.id, resource.id);
+                self.seek(planner, map_matrix, resource.loc, display_obstacle);
+                return Some(EventType::Nothing);
+            }
+        }
+        if base.keeps_charging(self.battery, self.battery_capacity) {
+            return Some(EventType::Nothing);
+        }
+        self.recharging = false;
+        None
+    }
 
-            for &(dx, dy) in &directions {
-                let new_x = x as i32 + dx;
-                let new_y = y as i32 + dy;
+    fn fuel_run<'a>(
+        &self,
+        finded_resources: &[u32],
+        resources: &'a HashMap<u32, Resource>,
+        base_flow: &FlowField,
+        base: &Base,
+    ) -> Option<&'a Resource> {
+        let fuel = base.fuel?;
+        finded_resources.iter()
+            .filter_map(|id| resources.get(id))
+            .filter(|resource| resource.kind == fuel && resource.remaining_quantity > 0 && self.space_for(fuel) > 0)
+            .filter_map(|resource| base_flow.distance(resource.loc).map(|distance| (distance, resource)))
+            .filter(|&(distance, _)| {
+                let round_trip = (distance as u16).saturating_mul(2 * MOVE_COST);
+                self.battery >= round_trip.saturating_add(EXTRACT_COST + RECHARGE_MARGIN)
+            })
+            .min_by_key(|&(distance, resource)| (distance, resource.id))
+            .map(|(_, resource)| resource)
+    }
 
-                if new_x >= 0 && new_x < rows as i32 && new_y >= 0 && new_y < cols as i32 {
-                    let new_x = new_x as u32;
-                    let new_y = new_y as u32;
+    fn load(&self) -> u16 {
+        self.inventory.iter().sum()
+    }
 
-                    if !visited.contains(&(new_x, new_y)) && map_matrix[new_x as usize][new_y as usize].display != display_obstacle {
-                        visited.insert((new_x, new_y));
-                        parent_map.insert((new_x, new_y), (x, y));
-                        queue.push_back((new_x, new_y));
-                    }
-                }
-            }
+    fn take_inventory(&mut self) -> Cargo {
+        let kinds = self.inventory.len();
+        self.delivering = false;
+        std::mem::replace(&mut self.inventory, vec![0; kinds])
+    }
+
+    fn space_for(&self, kind: ResourceKind) -> u16 {
+        let space = self.inventory_size.saturating_sub(self.load());
+        match self.cargo_limits.get(kind.0).copied().flatten() {
+            Some(limit) => space.min(limit.saturating_sub(self.inventory[kind.0])),
+            None => space,
         }
-        self.path = Some(Vec::new());
+    }
+
+    fn is_full(&self) -> bool {
+        self.load() >= self.inventory_size
     }
 
     fn step(&mut self) {
@@ -224,3 +497,58 @@ impl Gatherer {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ROWS: usize = 15;
+
+    fn docked(battery: u16, deposit: Localization) -> (Gatherer, HashMap<u32, Resource>, Base, FlowField, Vec<Vec<Cell>>) {
+        let registry = ResourceRegistry::default();
+        let mut map_matrix = vec![vec![Cell { display: ' ', explore: 0 }; ROWS]; ROWS];
+        let base = Base::new(ROWS as u32, ROWS as u32, &registry);
+        map_matrix[base.loc.x as usize][base.loc.y as usize].display = '#';
+        let base_flow = FlowField::new(&map_matrix, base.loc, '8', Topology::Bounded);
+        let mut gatherer = Gatherer::new(base.loc, 150, registry.cargo_limits(), Topology::Bounded, &mut IDGenerator::new()).unwrap();
+        gatherer.battery = battery;
+        gatherer.recharging = true;
+        let fuel = registry.fuel().unwrap();
+        let resource = Resource {
+            id: 9,
+            loc: deposit,
+            display: registry.get(fuel).glyph,
+            kind: fuel,
+            difficulty: 1.0,
+            initial_quantity: 40,
+            remaining_quantity: 40,
+        };
+        (gatherer, HashMap::from([(9, resource)]), base, base_flow, map_matrix)
+    }
+
+    fn recharge(battery: u16, deposit: Localization) -> (Gatherer, HashMap<u32, u32>) {
+        let (mut gatherer, resources, base, base_flow, map_matrix) = docked(battery, deposit);
+        let mut reservations = HashMap::new();
+        let mut planner = SpaceTimeTable::new();
+        let event = gatherer.recharge(&[9], &resources, &mut reservations, &mut planner, &map_matrix, &base_flow, &base, '8');
+        assert!(matches!(event, Some(EventType::Nothing)));
+        (gatherer, reservations)
+    }
+
+    #[test]
+    fn docked_gatherer_fetches_a_reachable_deposit_when_fuel_runs_out() {
+        let (gatherer, reservations) = recharge(40, Localization { x: 7, y: 2 });
+        assert!(!gatherer.recharging);
+        assert_eq!(gatherer.target, Some(9));
+        assert_eq!(reservations.get(&gatherer.id), Some(&9));
+        assert_eq!(gatherer.path.as_ref().map(|path| path.len()), Some(5));
+    }
+
+    #[test]
+    fn docked_gatherer_waits_when_the_deposit_is_out_of_reach() {
+        let (gatherer, reservations) = recharge(20, Localization { x: 7, y: 2 });
+        assert!(gatherer.recharging);
+        assert_eq!(gatherer.target, None);
+        assert!(reservations.is_empty());
+    }
+}
@@ -0,0 +1,139 @@
+use std::collections::{HashMap, VecDeque};
+use serde::Serialize;
+use crate::resources::{ResourceKind, ResourceRegistry};
+
+pub const MAX_ENTRIES: usize = 10_000;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Operation {
+    Extraction,
+    Transfer,
+    Deposit,
+    Spend,
+}
+
+impl Operation {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Operation::Extraction => "extraction",
+            Operation::Transfer => "transfer",
+            Operation::Deposit => "deposit",
+            Operation::Spend => "spend",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct LedgerEntry {
+    pub tick: u32,
+    pub robot: Option<u32>,
+    pub kind: ResourceKind,
+    pub operation: Operation,
+    pub amount: u64,
+}
+
+#[derive(Serialize)]
+pub struct LedgerRecord {
+    pub tick: u32,
+    pub robot: Option<u32>,
+    pub resource: String,
+    pub operation: &'static str,
+    pub amount: u64,
+}
+
+#[derive(Debug, Clone, Copy, Default, Serialize)]
+pub struct LedgerTotals {
+    pub extracted: u64,
+    pub transferred: u64,
+    pub deposited: u64,
+    pub spent: u64,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Ledger {
+    entries: VecDeque<LedgerEntry>,
+    totals: Vec<LedgerTotals>,
+}
+
+impl LedgerEntry {
+    pub fn record(&self, registry: &ResourceRegistry) -> LedgerRecord {
+        LedgerRecord {
+            tick: self.tick,
+            robot: self.robot,
+            resource: registry.get(self.kind).name.clone(),
+            operation: self.operation.name(),
+            amount: self.amount,
+        }
+    }
+}
+
+impl Ledger {
+    pub fn record(&mut self, tick: u32, robot: Option<u32>, kind: ResourceKind, operation: Operation, amount: u64) {
+        if amount == 0 {
+            return;
+        }
+        if self.totals.len() <= kind.0 {
+            self.totals.resize(kind.0 + 1, LedgerTotals::default());
+        }
+        let total = &mut self.totals[kind.0];
+        let counter = match operation {
+            Operation::Extraction => &mut total.extracted,
+            Operation::Transfer => &mut total.transferred,
+            Operation::Deposit => &mut total.deposited,
+            Operation::Spend => &mut total.spent,
+        };
+        *counter = counter.saturating_add(amount);
+        if self.entries.len() >= MAX_ENTRIES {
+            self.entries.pop_front();
+        }
+        self.entries.push_back(LedgerEntry { tick, robot, kind, operation, amount });
+    }
+
+    pub fn records(&self, registry: &ResourceRegistry, since: u32, robot: Option<u32>, limit: Option<usize>) -> Vec<LedgerRecord> {
+        let matching: Vec<&LedgerEntry> = self.entries.iter()
+            .filter(|entry| entry.tick >= since)
+            .filter(|entry| robot.is_none() || entry.robot == robot)
+            .collect();
+        let skip = matching.len().saturating_sub(limit.unwrap_or(usize::MAX));
+        matching[skip..].iter()
+            .map(|entry| entry.record(registry))
+            .collect()
+    }
+
+    pub fn totals(&self, registry: &ResourceRegistry) -> HashMap<String, LedgerTotals> {
+        registry.types.iter()
+            .enumerate()
+            .map(|(index, resource_type)| (resource_type.name.clone(), self.totals.get(index).copied().unwrap_or_default()))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn totals_survive_dropped_entries() {
+        let registry = ResourceRegistry::default();
+        let mut ledger = Ledger::default();
+        for tick in 0..(MAX_ENTRIES as u32 + 5) {
+            ledger.record(tick, Some(1), ResourceKind(0), Operation::Extraction, 2);
+        }
+        let records = ledger.records(&registry, 0, None, None);
+        assert_eq!(records.len(), MAX_ENTRIES);
+        assert_eq!(records[0].tick, 5);
+        assert_eq!(ledger.totals(&registry)["crystal"].extracted, 2 * (MAX_ENTRIES as u64 + 5));
+    }
+
+    #[test]
+    fn limit_keeps_the_latest_matching_entries() {
+        let registry = ResourceRegistry::default();
+        let mut ledger = Ledger::default();
+        for tick in 0..10 {
+            ledger.record(tick, Some(tick % 2), ResourceKind(1), Operation::Deposit, 1);
+        }
+        let ticks: Vec<u32> = ledger.records(&registry, 2, Some(1), Some(2)).iter().map(|record| record.tick).collect();
+        assert_eq!(ticks, vec![7, 9]);
+        assert_eq!(ledger.records(&registry, 0, None, Some(0)).len(), 0);
+    }
+}
@@ -0,0 +1,338 @@
+use std::fmt;
+use serde::{Deserialize, Serialize};
+use crate::resources::ResourceRegistry;
+use crate::game::Nature;
+
+pub const SPAWN_SYMBOL: char = '@';
+pub const EMPTY_SYMBOL: char = '.';
+pub const MIN_SIZE: u32 = 15;
+pub const MAX_SIZE: u32 = 200;
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct LevelResource {
+    pub kind: String,
+    pub x: u32,
+    pub y: u32,
+    pub quantity: Option<u16>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct LevelRobot {
+    pub kind: String,
+    pub x: u32,
+    pub y: u32,
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct Level {
+    pub rows: u32,
+    pub columns: u32,
+    #[serde(default)]
+    pub obstacles: Vec<(u32, u32)>,
+    #[serde(default)]
+    pub base: Vec<(u32, u32)>,
+    #[serde(default)]
+    pub resources: Vec<LevelResource>,
+    #[serde(default)]
+    pub spawns: Vec<(u32, u32)>,
+    #[serde(default)]
+    pub robots: Vec<LevelRobot>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+pub enum LevelSource {
+    Ascii(String),
+    Json(Level),
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Legend {
+    pub empty: char,
+    pub obstacle: char,
+    pub base: char,
+    pub scout: char,
+    pub gatherer: char,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum LevelError {
+    InvalidSize(u32, u32),
+    RaggedRow(u32),
+    UnknownSymbol(char, u32, u32),
+    OutOfBounds(u32, u32),
+    Overlap(u32, u32),
+    MissingBase,
+    UnknownResource(String),
+    UnknownRobot(String),
+    Unreachable(u32, u32),
+    Unreadable(String),
+    FileNotAllowed,
+}
+
+impl fmt::Display for LevelError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LevelError::InvalidSize(rows, columns) => write!(f, "Invalid level size {}x{} (between {} and {} expected).", rows, columns, MIN_SIZE, MAX_SIZE),
+            LevelError::RaggedRow(row) => write!(f, "Level row {} does not have the same width as the first row.", row),
+            LevelError::UnknownSymbol(symbol, x, y) => write!(f, "Unknown level symbol '{}' at ({}, {}).", symbol, x, y),
+            LevelError::OutOfBounds(x, y) => write!(f, "Level cell ({}, {}) is out of bounds.", x, y),
+            LevelError::Overlap(x, y) => write!(f, "Level cell ({}, {}) is used twice.", x, y),
+            LevelError::MissingBase => write!(f, "Level has no base tile."),
+            LevelError::UnknownResource(name) => write!(f, "Unknown resource kind : {}", name),
+            LevelError::UnknownRobot(name) => write!(f, "Unknown robot kind : {}", name),
+            LevelError::Unreachable(x, y) => write!(f, "Level cell ({}, {}) cannot be reached from the base.", x, y),
+            LevelError::Unreadable(message) => write!(f, "Cannot read level : {}", message),
+            LevelError::FileNotAllowed => write!(f, "Level files can only be loaded by the simulate command."),
+        }
+    }
+}
+
+impl LevelSource {
+    pub fn from_file(path: &std::path::Path) -> Result<Self, LevelError> {
+        let unreadable = |error: &dyn fmt::Display| LevelError::Unreadable(format!("{} : {}", path.display(), error));
+        let content = std::fs::read_to_string(path).map_err(|error| unreadable(&error))?;
+        if path.extension().is_some_and(|extension| extension == "json") {
+            serde_json::from_str(&content).map(LevelSource::Json).map_err(|error| unreadable(&error))
+        } else {
+            Ok(LevelSource::Ascii(content))
+        }
+    }
+
+    pub fn level(&self, legend: &Legend, registry: &ResourceRegistry) -> Result<Level, LevelError> {
+        match self {
+            LevelSource::Ascii(text) => Level::from_ascii(text, legend, registry),
+            LevelSource::Json(level) => Ok(level.clone()),
+        }
+    }
+}
+
+impl Legend {
+    pub fn symbols(&self) -> [char; 7] {
+        [self.empty, self.obstacle, self.base, self.scout, self.gatherer, SPAWN_SYMBOL, EMPTY_SYMBOL]
+    }
+}
+
+impl Level {
+    pub fn from_ascii(text: &str, legend: &Legend, registry: &ResourceRegistry) -> Result<Self, LevelError> {
+        let lines: Vec<Vec<char>> = text.lines()
+            .map(|line| line.trim_end_matches('\r').chars().collect())
+            .collect();
+        let columns = lines.first().map_or(0, |line| line.len()) as u32;
+        let mut level = Level {
+            rows: lines.len() as u32,
+            columns,
+            ..Level::default()
+        };
+        for (x, line) in lines.iter().enumerate() {
+            let x = x as u32;
+            if line.len() as u32 != columns {
+                return Err(LevelError::RaggedRow(x));
+            }
+            for (y, &symbol) in line.iter().enumerate() {
+                let y = y as u32;
+                if symbol == legend.obstacle {
+                    level.obstacles.push((x, y));
+                } else if symbol == legend.base {
+                    level.base.push((x, y));
+                } else if symbol == SPAWN_SYMBOL {
+                    level.spawns.push((x, y));
+                } else if symbol == legend.scout || symbol == legend.gatherer {
+                    let kind = if symbol == legend.scout { "scout" } else { "gatherer" };
+                    level.robots.push(LevelRobot { kind: kind.to_string(), x, y });
+                } else if let Some(resource_type) = registry.types.iter().find(|resource_type| resource_type.glyph == symbol) {
+                    level.resources.push(LevelResource { kind: resource_type.name.clone(), x, y, quantity: None });
+                } else if symbol != legend.empty && symbol != EMPTY_SYMBOL {
+                    return Err(LevelError::UnknownSymbol(symbol, x, y));
+                }
+            }
+        }
+        Ok(level)
+    }
+
+    pub fn to_ascii(&self, legend: &Legend, registry: &ResourceRegistry) -> (String, usize) {
+        let mut grid = vec![vec![EMPTY_SYMBOL; self.columns as usize]; self.rows as usize];
+        let mut omitted = 0;
+        let mut set = |x: u32, y: u32, symbol: char| {
+            match grid.get_mut(x as usize).and_then(|row| row.get_mut(y as usize)) {
+                Some(cell) if *cell == EMPTY_SYMBOL => *cell = symbol,
+                _ => omitted += 1,
+            }
+        };
+        for &(x, y) in self.obstacles.iter() {
+            set(x, y, legend.obstacle);
+        }
+        for &(x, y) in self.base.iter() {
+            set(x, y, legend.base);
+        }
+        for resource in self.resources.iter() {
+            if let Some(kind) = registry.kind(&resource.kind) {
+                set(resource.x, resource.y, registry.get(kind).glyph);
+            }
+        }
+        for robot in self.robots.iter() {
+            match Nature::from_str(&robot.kind) {
+                Some(Nature::Scout) => set(robot.x, robot.y, legend.scout),
+                Some(Nature::Gatherer) => set(robot.x, robot.y, legend.gatherer),
+                None => {}
+            }
+        }
+        for &(x, y) in self.spawns.iter() {
+            set(x, y, SPAWN_SYMBOL);
+        }
+        let mut text = String::new();
+        for row in grid {
+            text.extend(row);
+            text.push('\n');
+        }
+        (text, omitted)
+    }
+
+    pub fn validate(&self, registry: &ResourceRegistry) -> Result<(), LevelError> {
+        if !(MIN_SIZE..=MAX_SIZE).contains(&self.rows) || !(MIN_SIZE..=MAX_SIZE).contains(&self.columns) {
+            return Err(LevelError::InvalidSize(self.rows, self.columns));
+        }
+        if self.base.is_empty() {
+            return Err(LevelError::MissingBase);
+        }
+        let mut used = vec![vec![false; self.columns as usize]; self.rows as usize];
+        let mut claim = |x: u32, y: u32| -> Result<(), LevelError> {
+            if x >= self.rows || y >= self.columns {
+                return Err(LevelError::OutOfBounds(x, y));
+            }
+            if used[x as usize][y as usize] {
+                return Err(LevelError::Overlap(x, y));
+            }
+            used[x as usize][y as usize] = true;
+            Ok(())
+        };
+        for &(x, y) in self.obstacles.iter().chain(self.base.iter()) {
+            claim(x, y)?;
+        }
+        let base_or_resource = |x: u32, y: u32| {
+            self.base.contains(&(x, y)) || self.resources.iter().any(|resource| (resource.x, resource.y) == (x, y))
+        };
+        for resource in self.resources.iter() {
+            if registry.kind(&resource.kind).is_none() {
+                return Err(LevelError::UnknownResource(resource.kind.clone()));
+            }
+            claim(resource.x, resource.y)?;
+        }
+        for robot in self.robots.iter() {
+            if Nature::from_str(&robot.kind).is_none() {
+                return Err(LevelError::UnknownRobot(robot.kind.clone()));
+            }
+        }
+        for &(x, y) in self.spawns.iter() {
+            if x >= self.rows || y >= self.columns {
+                return Err(LevelError::OutOfBounds(x, y));
+            }
+            if used[x as usize][y as usize] && !self.base.contains(&(x, y)) {
+                return Err(LevelError::Overlap(x, y));
+            }
+        }
+        for robot in self.robots.iter() {
+            if robot.x >= self.rows || robot.y >= self.columns {
+                return Err(LevelError::OutOfBounds(robot.x, robot.y));
+            }
+            if used[robot.x as usize][robot.y as usize] && !base_or_resource(robot.x, robot.y) {
+                return Err(LevelError::Overlap(robot.x, robot.y));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LEGEND: Legend = Legend { empty: ' ', obstacle: '8', base: '#', scout: 'S', gatherer: 'G' };
+
+    fn grid(cells: &[(u32, u32, char)]) -> String {
+        let mut rows = vec![vec!['.'; MIN_SIZE as usize]; MIN_SIZE as usize];
+        for &(x, y, symbol) in cells {
+            rows[x as usize][y as usize] = symbol;
+        }
+        rows.into_iter().map(|row| row.into_iter().collect::<String>() + "\n").collect()
+    }
+
+    #[test]
+    fn from_ascii_reads_every_symbol() {
+        let text = grid(&[(0, 0, '8'), (7, 7, '#'), (3, 4, 'C'), (8, 8, '@'), (6, 7, 'S'), (2, 2, 'G'), (1, 1, ' ')]);
+        let level = Level::from_ascii(&text, &LEGEND, &ResourceRegistry::default()).unwrap();
+        assert_eq!((level.rows, level.columns), (MIN_SIZE, MIN_SIZE));
+        assert_eq!(level.obstacles, vec![(0, 0)]);
+        assert_eq!(level.base, vec![(7, 7)]);
+        assert_eq!(level.spawns, vec![(8, 8)]);
+        assert_eq!(level.resources.len(), 1);
+        assert_eq!((level.resources[0].kind.as_str(), level.resources[0].x, level.resources[0].y), ("crystal", 3, 4));
+        let robots: Vec<(&str, u32, u32)> = level.robots.iter().map(|robot| (robot.kind.as_str(), robot.x, robot.y)).collect();
+        assert_eq!(robots, vec![("gatherer", 2, 2), ("scout", 6, 7)]);
+    }
+
+    #[test]
+    fn from_ascii_rejects_ragged_rows_and_unknown_symbols() {
+        let registry = ResourceRegistry::default();
+        assert_eq!(Level::from_ascii("...\r\n..\n", &LEGEND, &registry).unwrap_err(), LevelError::RaggedRow(1));
+        assert_eq!(Level::from_ascii("..\n.X\n", &LEGEND, &registry).unwrap_err(), LevelError::UnknownSymbol('X', 1, 1));
+    }
+
+    #[test]
+    fn validate_checks_size_base_and_overlaps() {
+        let registry = ResourceRegistry::default();
+        let level = Level::from_ascii(&grid(&[(7, 7, '#'), (3, 3, 'E')]), &LEGEND, &registry).unwrap();
+        assert_eq!(level.validate(&registry), Ok(()));
+
+        let small = Level { rows: MIN_SIZE - 1, ..level.clone() };
+        assert_eq!(small.validate(&registry), Err(LevelError::InvalidSize(MIN_SIZE - 1, MIN_SIZE)));
+        let no_base = Level { base: vec![], ..level.clone() };
+        assert_eq!(no_base.validate(&registry), Err(LevelError::MissingBase));
+
+        let mut blocked = level.clone();
+        blocked.obstacles.push((3, 3));
+        assert_eq!(blocked.validate(&registry), Err(LevelError::Overlap(3, 3)));
+        let mut outside = level.clone();
+        outside.spawns.push((MIN_SIZE, 0));
+        assert_eq!(outside.validate(&registry), Err(LevelError::OutOfBounds(MIN_SIZE, 0)));
+        let mut unknown = level.clone();
+        unknown.resources.push(LevelResource { kind: "gold".to_string(), x: 1, y: 1, quantity: None });
+        assert_eq!(unknown.validate(&registry), Err(LevelError::UnknownResource("gold".to_string())));
+    }
+
+    #[test]
+    fn validate_places_robots_on_base_or_resources_and_spawns_on_base() {
+        let registry = ResourceRegistry::default();
+        let mut level = Level::from_ascii(&grid(&[(7, 7, '#'), (3, 3, 'C'), (0, 0, '8')]), &LEGEND, &registry).unwrap();
+        level.robots.push(LevelRobot { kind: "gatherer".to_string(), x: 3, y: 3 });
+        level.robots.push(LevelRobot { kind: "scout".to_string(), x: 7, y: 7 });
+        level.spawns.push((7, 7));
+        assert_eq!(level.validate(&registry), Ok(()));
+
+        let mut spawn_on_resource = level.clone();
+        spawn_on_resource.spawns.push((3, 3));
+        assert_eq!(spawn_on_resource.validate(&registry), Err(LevelError::Overlap(3, 3)));
+        let mut robot_on_obstacle = level.clone();
+        robot_on_obstacle.robots.push(LevelRobot { kind: "scout".to_string(), x: 0, y: 0 });
+        assert_eq!(robot_on_obstacle.validate(&registry), Err(LevelError::Overlap(0, 0)));
+        let mut unknown_robot = level;
+        unknown_robot.robots.push(LevelRobot { kind: "drone".to_string(), x: 1, y: 1 });
+        assert_eq!(unknown_robot.validate(&registry), Err(LevelError::UnknownRobot("drone".to_string())));
+    }
+
+    #[test]
+    fn to_ascii_round_trips_and_counts_omitted_entries() {
+        let registry = ResourceRegistry::default();
+        let text = grid(&[(0, 0, '8'), (7, 7, '#'), (3, 4, 'C'), (8, 8, '@'), (6, 7, 'S'), (2, 2, 'G')]);
+        let level = Level::from_ascii(&text, &LEGEND, &registry).unwrap();
+        assert_eq!(level.to_ascii(&LEGEND, &registry), (text, 0));
+
+        let mut stacked = level;
+        stacked.robots.push(LevelRobot { kind: "gatherer".to_string(), x: 3, y: 4 });
+        stacked.robots.push(LevelRobot { kind: "scout".to_string(), x: 7, y: 7 });
+        stacked.spawns.push((7, 7));
+        let (ascii, omitted) = stacked.to_ascii(&LEGEND, &registry);
+        assert_eq!(omitted, 3);
+        assert_eq!(Level::from_ascii(&ascii, &LEGEND, &registry).unwrap().robots.len(), 2);
+    }
+}
@@ -3,16 +3,30 @@ use axum::{
     routing::get,
     routing::post,
     response::Json,
+    response::IntoResponse,
     extract::Json as AxumJson,
     extract::Path,
+    extract::Query,
+    http::StatusCode,
 };
 use tower_http::cors::{Any, CorsLayer};
 use std::net::SocketAddr;
 use std::sync::{Arc, Mutex};
 use std::collections::HashMap;
 use uuid::Uuid;
-use game::Game;
+use game::{Game, Localization, Nature, RobotState};
+use production::{BuildState, Recipe};
+use scouts::ExplorationMode;
+use controller::BasePolicy;
+use upgrades::{UpgradeKind, Upgrades};
+use resources::{PlacementError, ResourceRegistry, ResourceRule, ResourceType};
 use events::EventType;
+use ledger::{LedgerRecord, LedgerTotals};
+use terrain::TerrainSettings;
+use map_generator::{BasePlacement, GeneratorKind, Reachability};
+use topology::Topology;
+use level::{Legend, Level, LevelError, LevelResource, LevelSource};
+use metrics::{MapAcceptance, MapMetrics};
 use serde::{Deserialize, Serialize};
 
 mod game;
@@ -21,6 +35,17 @@ mod scouts;
 mod resources;
 mod id_generator;
 mod events;
+mod flow_field;
+mod planner;
+mod production;
+mod controller;
+mod upgrades;
+mod ledger;
+mod terrain;
+mod map_generator;
+mod level;
+mod metrics;
+mod topology;
 
 
 type SharedGames = Arc<Mutex<HashMap<String, Game>>>;
@@ -38,47 +63,360 @@ struct ResetRequest {
     base_display: Option<char>,
     scout_display: Option<char>,
     gatherer_display: Option<char>,
+    exploration: Option<String>,
+    base_stacking: Option<bool>,
+    battery_capacity: Option<u16>,
+    policy: Option<String>,
+    max_robots: Option<u16>,
+    resource_types: Option<Vec<ResourceType>>,
+    resource_rules: Option<HashMap<String, ResourceRule>>,
+    terrain: Option<TerrainSettings>,
+    generator: Option<String>,
+    reachability: Option<String>,
+    level: Option<LevelSource>,
+    level_file: Option<String>,
+    acceptance: Option<MapAcceptance>,
+    base: Option<BaseRequest>,
+    spawns: Option<Vec<(u32, u32)>>,
+    topology: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum BaseRequest {
+    Named(String),
+    At(u32, u32),
+}
+
+#[derive(Debug)]
+enum SetupError {
+    Placement(PlacementError),
+    Level(LevelError),
+    Rejected(u32),
+    Spawn(u32, u32),
+    Base(u32, u32),
+    Registry(&'static str),
+    Crowded,
+}
+
+impl From<PlacementError> for SetupError {
+    fn from(error: PlacementError) -> Self {
+        SetupError::Placement(error)
+    }
+}
+
+impl From<LevelError> for SetupError {
+    fn from(error: LevelError) -> Self {
+        SetupError::Level(error)
+    }
+}
+
+impl std::fmt::Display for SetupError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            SetupError::Placement(error) => write!(f, "{}", error),
+            SetupError::Level(error) => write!(f, "{}", error),
+            SetupError::Rejected(attempts) => write!(f, "No map satisfied the acceptance bounds after {} attempts.", attempts),
+            SetupError::Spawn(x, y) => write!(f, "Spawn point ({}, {}) is out of bounds or cannot be reached from the base.", x, y),
+            SetupError::Base(x, y) => write!(f, "Base location ({}, {}) is out of bounds.", x, y),
+            SetupError::Registry(message) => write!(f, "Invalid resource types : {}", message),
+            SetupError::Crowded => write!(f, "Not enough free spawn points or base tiles for every robot without base_stacking."),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct BuildRequest {
+    kind: String,
+}
+
+#[derive(Deserialize)]
+struct UpgradeRequest {
+    kind: String,
+}
+
+#[derive(Deserialize)]
+struct LedgerQuery {
+    since: Option<u32>,
+    robot: Option<u32>,
+    limit: Option<usize>,
+}
+
+#[derive(Deserialize)]
+struct ExportQuery {
+    format: Option<String>,
 }
 
 #[derive(Serialize)]
-struct StateResponse {
+struct PreviewResponse {
+    seed: u64,
     map: Vec<Vec<char>>,
-    crystal_count: u16,
-    energy_count: u16,
+    resources: Vec<LevelResource>,
+    metrics: MapMetrics,
 }
 
-fn create_new_game(
-    rows: u32,
-    columns: u32,
+#[derive(Serialize)]
+struct LedgerResponse {
+    entries: Vec<LedgerRecord>,
+    totals: HashMap<String, LedgerTotals>,
+}
+
+#[derive(Serialize)]
+struct SimulationSummary {
+    config: String,
     seed: u64,
-    gatherers: u8,
-    scouts: u8,
-    resources: u8,
-    empty_display: char,
-    obstacle_display: char,
-    base_display: char,
-    scout_display: char,
-    gatherer_display: char
-) -> Game {
-    let mut id_generator = id_generator::IDGenerator::new();
-    let mut map = Game::new(rows, columns, seed, empty_display, obstacle_display, base_display,scout_display,gatherer_display);
-    map.generate_map_obstacles();
-    map.generate_resources(&mut id_generator, resources);
+    policy: Option<&'static str>,
+    ticks: u32,
+    stock: HashMap<String, u64>,
+    ledger: HashMap<String, LedgerTotals>,
+    scouts: usize,
+    gatherers: usize,
+    disabled: usize,
+    idle: usize,
+    explored: f32,
+}
+
+#[derive(Serialize)]
+struct StateResponse {
+    seed: u64,
+    map: Vec<Vec<char>>,
+    base_distance: Vec<Vec<Option<u32>>>,
+    robots: Vec<RobotState>,
+    build_queue: Vec<BuildState>,
+    upgrades: Upgrades,
+    stock: HashMap<String, u64>,
+    crystal_count: u64,
+    energy_count: u64,
+}
+
+fn build_map(body: &ResetRequest) -> Result<(Game, Option<Level>), SetupError> {
+    let Some(acceptance) = body.acceptance.filter(|_| body.level.is_none()) else {
+        return build_map_with_seed(body, body.seed);
+    };
+    for attempt in 0..acceptance.attempts() {
+        match build_map_with_seed(body, acceptance.seed_for(body.seed, attempt)) {
+            Ok((map, level)) if acceptance.accepts(&map.map_metrics()) => return Ok((map, level)),
+            Ok(_) | Err(SetupError::Placement(_)) | Err(SetupError::Spawn(_, _)) => continue,
+            Err(error) => return Err(error),
+        }
+    }
+    Err(SetupError::Rejected(acceptance.attempts()))
+}
+
+fn build_map_with_seed(body: &ResetRequest, seed: u64) -> Result<(Game, Option<Level>), SetupError> {
+    if body.level_file.is_some() {
+        return Err(LevelError::FileNotAllowed.into());
+    }
+    let registry = match body.resource_types.clone() {
+        Some(types) => ResourceRegistry::new(types).map_err(SetupError::Registry)?,
+        None => ResourceRegistry::default(),
+    };
+    let pays_costs = [Nature::Scout, Nature::Gatherer].iter().all(|&nature| registry.resolves(Recipe::for_nature(nature).cost))
+        && UpgradeKind::ALL.iter().all(|kind| registry.resolves(kind.base_cost()));
+    if !pays_costs {
+        return Err(SetupError::Registry("Resource types must include crystal and energy, which pay for robots and upgrades."));
+    }
+    let legend = Legend {
+        empty: body.empty_display.unwrap_or(' '),
+        obstacle: body.obstacle_display.unwrap_or('8'),
+        base: body.base_display.unwrap_or('#'),
+        scout: body.scout_display.unwrap_or('S'),
+        gatherer: body.gatherer_display.unwrap_or('G'),
+    };
+    registry.check_glyphs(&legend.symbols()).map_err(SetupError::Registry)?;
+    let level = match &body.level {
+        Some(source) => {
+            let level = source.level(&legend, &registry)?;
+            level.validate(&registry)?;
+            Some(level)
+        }
+        None => None,
+    };
+    let (rows, columns) = match &level {
+        Some(level) => (level.rows, level.columns),
+        None => (body.rows.clamp(15, 200), body.columns.clamp(15, 200)),
+    };
+    let mut map = Game::new(
+        rows,
+        columns,
+        seed,
+        legend.empty,
+        legend.obstacle,
+        legend.base,
+        legend.scout,
+        legend.gatherer,
+    );
+    map.exploration_mode = body.exploration.as_deref()
+        .and_then(ExplorationMode::from_str)
+        .unwrap_or(ExplorationMode::Local);
+    map.base_stacking = body.base_stacking.unwrap_or(true);
+    map.battery_capacity = body.battery_capacity.unwrap_or(150).clamp(10, 1000);
+    map.set_registry(registry);
+    if let Some(rules) = body.resource_rules.clone() {
+        map.set_resource_rules(rules);
+    }
+    map.terrain = body.terrain.unwrap_or_default().clamped(rows, columns);
+    map.generator = body.generator.as_deref()
+        .and_then(GeneratorKind::from_str)
+        .unwrap_or(GeneratorKind::Perlin);
+    map.reachability = body.reachability.as_deref()
+        .and_then(Reachability::from_str)
+        .unwrap_or(Reachability::Carve);
+    map.topology = body.topology.as_deref()
+        .and_then(Topology::from_str)
+        .unwrap_or(Topology::Bounded);
+    map.controller = body.policy.as_deref()
+        .and_then(BasePolicy::from_str)
+        .map(|mut policy| {
+            if let Some(max_robots) = body.max_robots {
+                policy.max_robots = max_robots.clamp(1, 100) as usize;
+            }
+            policy
+        });
+    match &level {
+        Some(level) => map.load_level(level)?,
+        None => {
+            map.base_placement = match &body.base {
+                Some(BaseRequest::Named(name)) => BasePlacement::from_str(name).unwrap_or(BasePlacement::Center),
+                Some(BaseRequest::At(x, y)) if *x >= rows || *y >= columns => return Err(SetupError::Base(*x, *y)),
+                Some(BaseRequest::At(x, y)) => BasePlacement::At(*x, *y),
+                None => BasePlacement::Center,
+            };
+            for &(x, y) in body.spawns.iter().flatten() {
+                if x >= rows || y >= columns {
+                    return Err(SetupError::Spawn(x, y));
+                }
+                map.spawn_points.push(Localization { x, y });
+            }
+            map.generate_map_obstacles();
+            for spawn in map.spawn_points.iter() {
+                if map.base_flow.read().unwrap().distance(*spawn).is_none() {
+                    return Err(SetupError::Spawn(spawn.x, spawn.y));
+                }
+            }
+            map.generate_resources(body.resources.clamp(1, 50))?;
+        }
+    }
+    Ok((map, level))
+}
+
+fn create_new_game(body: &ResetRequest) -> Result<Game, SetupError> {
+    let (mut map, level) = build_map(body)?;
+    let min_scouts = match &level {
+        Some(level) => {
+            map.add_level_robots(level)?;
+            0
+        }
+        None => 1,
+    };
     
-    for _ in 0..scouts {
-        map.add_scout(rows / 2, columns / 2, &mut id_generator);
+    let scouts = body.scouts.clamp(min_scouts, 15) as usize;
+    for index in 0..scouts {
+        let loc = map.robot_spawn(index).ok_or(SetupError::Crowded)?;
+        map.add_scout(loc.x, loc.y);
     }
 
-    for _ in 0..gatherers {
-        map.add_gatherer(rows / 2, columns / 2, &mut id_generator);
+    for index in 0..body.gatherers.clamp(0, 15) as usize {
+        let loc = map.robot_spawn(scouts + index).ok_or(SetupError::Crowded)?;
+        map.add_gatherer(loc.x, loc.y);
     }
 
-    map
+    Ok(map)
+}
+
+fn game_from_config(path: &str) -> Result<Game, String> {
+    let mut body = match std::fs::read_to_string(path).map(|content| serde_json::from_str::<ResetRequest>(&content)) {
+        Ok(Ok(body)) => body,
+        Ok(Err(error)) => return Err(format!("Invalid config {} : {}", path, error)),
+        Err(error) => return Err(format!("Cannot read {} : {}", path, error)),
+    };
+    if let Some(level_file) = body.level_file.take() {
+        let level_path = std::path::Path::new(path).with_file_name(level_file);
+        let source = LevelSource::from_file(&level_path)
+            .map_err(|error| format!("Cannot load level for {} : {}", path, error))?;
+        body.level = Some(source);
+    }
+    create_new_game(&body).map_err(|error| format!("Cannot create game from {} : {}", path, error))
+}
+
+fn run_export(args: &[String]) {
+    let (Some(ticks), Some(config), Some(output)) = (args.first().and_then(|ticks| ticks.parse::<u32>().ok()), args.get(1), args.get(2)) else {
+        eprintln!("Usage: rustBotProject export <ticks> <config.json> <level.txt|level.json>");
+        return;
+    };
+    let mut game = match game_from_config(config) {
+        Ok(game) => game,
+        Err(error) => {
+            eprintln!("{}", error);
+            return;
+        }
+    };
+    for _ in 0..ticks {
+        game.handle_event(EventType::Tick);
+    }
+    let level = game.export_level();
+    let content = if output.ends_with(".json") {
+        serde_json::to_string(&level).unwrap()
+    } else {
+        let (text, omitted) = level.to_ascii(&game.legend(), &game.registry);
+        if omitted > 0 {
+            eprintln!("Warning : {} robots, resources or spawn points share a cell and are missing from the ASCII level, use a .json output to keep them.", omitted);
+        }
+        text
+    };
+    if let Err(error) = std::fs::write(output, content) {
+        eprintln!("Cannot write {} : {}", output, error);
+    }
+}
+
+fn run_headless(args: &[String]) {
+    let Some(ticks) = args.first().and_then(|ticks| ticks.parse::<u32>().ok()) else {
+        eprintln!("Usage: rustBotProject simulate <ticks> <config.json>...");
+        return;
+    };
+    for path in &args[1..] {
+        let mut game = match game_from_config(path) {
+            Ok(game) => game,
+            Err(error) => {
+                eprintln!("{}", error);
+                continue;
+            }
+        };
+        for _ in 0..ticks {
+            game.handle_event(EventType::Tick);
+        }
+        let observation = game.observe();
+        let states = game.robot_states();
+        let summary = SimulationSummary {
+            config: path.clone(),
+            seed: game.seed,
+            policy: game.controller.map(|policy| policy.name),
+            ticks,
+            stock: game.stock(),
+            ledger: game.ledger.totals(&game.registry),
+            scouts: observation.scouts,
+            gatherers: observation.gatherers,
+            disabled: states.iter().filter(|state| state.disabled).count(),
+            idle: states.iter().filter(|state| state.idle).count(),
+            explored: observation.explored,
+        };
+        println!("{}", serde_json::to_string(&summary).unwrap());
+    }
 }
 
 #[tokio::main]
 async fn main() {
-    let games: Arc<Mutex<HashMap<String, Game>>> = Arc::new(Mutex::new(HashMap::new()));
+    let args: Vec<String> = std::env::args().collect();
+    if args.get(1).is_some_and(|command| command == "simulate") {
+        run_headless(&args[2..]);
+        return;
+    }
+    if args.get(1).is_some_and(|command| command == "export") {
+        run_export(&args[2..]);
+        return;
+    }
+
+    let games: SharedGames = Arc::new(Mutex::new(HashMap::new()));
 
     let cors = CorsLayer::new()
         .allow_origin(Any)
@@ -95,16 +433,28 @@ async fn main() {
                     if let Some(game) = map.get_mut(&id) {
                         game.handle_event(EventType::Tick);
                         let response = StateResponse {
+                            seed: game.seed,
                             map: game.generate_display().iter().map(|row| {
                                 row.iter().map(|cell| cell.display).collect::<Vec<_>>()
                             }).collect(),
-                            crystal_count: game.base.crystal,
-                            energy_count: game.base.energy,
+                            base_distance: game.generate_base_distance_layer(),
+                            robots: game.robot_states(),
+                            build_queue: game.build_states(),
+                            upgrades: *game.upgrades.read().unwrap(),
+                            stock: game.stock(),
+                            crystal_count: game.stock_of("crystal"),
+                            energy_count: game.stock_of("energy"),
                         };
                         Json(response)
                     } else {
                         Json(StateResponse {
+                            seed: 0,
                             map: vec![],
+                            base_distance: vec![],
+                            robots: vec![],
+                            build_queue: vec![],
+                            upgrades: Upgrades::default(),
+                            stock: HashMap::new(),
                             crystal_count: 0,
                             energy_count: 0,
                         })
@@ -112,25 +462,69 @@ async fn main() {
                 }
             }
         }))
+        .route("/ledger/:id", get({
+            let games = Arc::clone(&games);
+            move |Path(id): Path<String>, Query(query): Query<LedgerQuery>| {
+                let games = Arc::clone(&games);
+                async move {
+                    let map = games.lock().unwrap();
+                    if let Some(game) = map.get(&id) {
+                        Json(LedgerResponse {
+                            entries: game.ledger.records(&game.registry, query.since.unwrap_or(0), query.robot, query.limit),
+                            totals: game.ledger.totals(&game.registry),
+                        })
+                    } else {
+                        Json(LedgerResponse {
+                            entries: vec![],
+                            totals: HashMap::new(),
+                        })
+                    }
+                }
+            }
+        }))
+        .route("/export/:id", get({
+            let games = Arc::clone(&games);
+            move |Path(id): Path<String>, Query(query): Query<ExportQuery>| {
+                let games = Arc::clone(&games);
+                async move {
+                    let map = games.lock().unwrap();
+                    let Some(game) = map.get(&id) else {
+                        return Json("Invalid game ID.").into_response();
+                    };
+                    let level = game.export_level();
+                    match query.format.as_deref().unwrap_or("json") {
+                        "json" => Json(level).into_response(),
+                        "ascii" => {
+                            let (text, omitted) = level.to_ascii(&game.legend(), &game.registry);
+                            ([("x-level-omitted", omitted.to_string())], text).into_response()
+                        }
+                        _ => Json("Unknown export format.").into_response(),
+                    }
+                }
+            }
+        }))
+        .route("/preview", post(|AxumJson(body): AxumJson<ResetRequest>| async move {
+            let (map, _) = match build_map(&body) {
+                Ok(built) => built,
+                Err(error) => return Err((StatusCode::UNPROCESSABLE_ENTITY, Json(error.to_string()))),
+            };
+            Ok(Json(PreviewResponse {
+                seed: map.seed,
+                map: map.generate_terrain_display(),
+                resources: map.export_level().resources,
+                metrics: map.map_metrics(),
+            }))
+        }))
         .route("/start", post({
             let games = Arc::clone(&games);
             move |AxumJson(body): AxumJson<ResetRequest>| {
                 let games = Arc::clone(&games);
                 async move {
                     let game_id = Uuid::new_v4().to_string();
-                    let new_game = create_new_game(
-                        body.rows.clamp(15, 200),
-                        body.columns.clamp(15, 200),
-                        body.seed,
-                        body.gatherers.clamp(0, 15),
-                        body.scouts.clamp(1, 15),
-                        body.resources.clamp(1, 50),
-                        body.empty_display.unwrap_or(' '),
-                        body.obstacle_display.unwrap_or('8'),
-                        body.base_display.unwrap_or('#'),
-                        body.scout_display.unwrap_or('S'),
-                        body.gatherer_display.unwrap_or('G'),
-                    );
+                    let new_game = match create_new_game(&body) {
+                        Ok(game) => game,
+                        Err(error) => return Err((StatusCode::UNPROCESSABLE_ENTITY, Json(error.to_string()))),
+                    };
                     {
                         let mut map_guard = games.lock().unwrap();
                         map_guard.insert(game_id.clone(), new_game);
@@ -148,7 +542,7 @@ async fn main() {
                             }
                         }
                     });
-                    Json(game_id)
+                    Ok(Json(game_id))
                 }
             }
         }))
@@ -157,25 +551,50 @@ async fn main() {
             move |Path(id): Path<String>, AxumJson(body): AxumJson<ResetRequest>| {
                 let games = Arc::clone(&games);
                 async move {
-                    let new_game = create_new_game(
-                        body.rows.clamp(15, 200),
-                        body.columns.clamp(15, 200),
-                        body.seed,
-                        body.gatherers.clamp(0, 15),
-                        body.scouts.clamp(1, 15),
-                        body.resources.clamp(1, 50),
-                        body.empty_display.unwrap_or(' '),
-                        body.obstacle_display.unwrap_or('8'),
-                        body.base_display.unwrap_or('#'),
-                        body.scout_display.unwrap_or('S'),
-                        body.gatherer_display.unwrap_or('G'),
-                    );
+                    let new_game = match create_new_game(&body) {
+                        Ok(game) => game,
+                        Err(error) => return Err((StatusCode::UNPROCESSABLE_ENTITY, Json(error.to_string()))),
+                    };
                     let mut map = games.lock().unwrap();
                     if map.contains_key(&id) {
                         map.insert(id.clone(), new_game);
-                        Json("Game has been reset.")
+                        Ok(Json("Game has been reset."))
                     } else {
-                        Json("Invalid game ID.")
+                        Ok(Json("Invalid game ID."))
+                    }
+                }
+            }
+        }))
+        .route("/build/:id", post({
+            let games = Arc::clone(&games);
+            move |Path(id): Path<String>, AxumJson(body): AxumJson<BuildRequest>| {
+                let games = Arc::clone(&games);
+                async move {
+                    let mut map = games.lock().unwrap();
+                    match (map.get_mut(&id), Nature::from_str(&body.kind)) {
+                        (Some(game), Some(nature)) => match game.order_robot(nature) {
+                            Ok(()) => Json("Robot added to build queue."),
+                            Err(message) => Json(message),
+                        },
+                        (Some(_), None) => Json("Unknown robot kind."),
+                        (None, _) => Json("Invalid game ID."),
+                    }
+                }
+            }
+        }))
+        .route("/upgrade/:id", post({
+            let games = Arc::clone(&games);
+            move |Path(id): Path<String>, AxumJson(body): AxumJson<UpgradeRequest>| {
+                let games = Arc::clone(&games);
+                async move {
+                    let mut map = games.lock().unwrap();
+                    match (map.get_mut(&id), UpgradeKind::from_str(&body.kind)) {
+                        (Some(game), Some(kind)) => match game.purchase_upgrade(kind) {
+                            Ok(_) => Json("Upgrade purchased."),
+                            Err(message) => Json(message),
+                        },
+                        (Some(_), None) => Json("Unknown upgrade kind."),
+                        (None, _) => Json("Invalid game ID."),
                     }
                 }
             }
@@ -203,3 +622,34 @@ async fn main() {
         .await
         .unwrap();
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(json: &str) -> ResetRequest {
+        serde_json::from_str(json).unwrap()
+    }
+
+    #[test]
+    fn small_levels_are_rejected_before_building_the_game() {
+        let body = request(r#"{ "columns": 20, "rows": 20, "gatherers": 1, "scouts": 1, "resources": 1, "seed": 1, "level": "...\n.#.\n...\n" }"#);
+        assert!(matches!(build_map_with_seed(&body, 1), Err(SetupError::Level(LevelError::InvalidSize(3, 3)))));
+    }
+
+    #[test]
+    fn huge_levels_are_rejected_before_building_the_game() {
+        let body = request(r#"{ "columns": 20, "rows": 20, "gatherers": 1, "scouts": 1, "resources": 1, "seed": 1, "level": { "rows": 100000, "columns": 100000, "base": [[0, 0]] } }"#);
+        assert!(matches!(build_map_with_seed(&body, 1), Err(SetupError::Level(LevelError::InvalidSize(100000, 100000)))));
+    }
+
+    #[test]
+    fn base_coordinates_outside_the_map_are_rejected() {
+        let body = request(r#"{ "columns": 20, "rows": 15, "gatherers": 1, "scouts": 1, "resources": 1, "seed": 1, "base": [500, 500] }"#);
+        assert!(matches!(build_map_with_seed(&body, 1), Err(SetupError::Base(500, 500))));
+        let body = request(r#"{ "columns": 20, "rows": 15, "gatherers": 1, "scouts": 1, "resources": 1, "seed": 1, "base": [14, 19] }"#);
+        let (map, _) = build_map_with_seed(&body, 1).unwrap();
+        let base = map.base.read().unwrap().loc;
+        assert_eq!((base.x, base.y), (12, 17));
+    }
+}
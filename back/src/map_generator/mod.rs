@@ -0,0 +1,310 @@
+use rand::prelude::*;
+use crate::terrain::TerrainSettings;
+use crate::game::Localization;
+use crate::topology::Topology;
+
+const CAVE_FILL: f64 = 0.45;
+const CAVE_STEPS: usize = 4;
+const ROCK_MAX_RADIUS: i32 = 2;
+const BASE_MARGIN: u32 = 2;
+
+pub trait MapGenerator {
+    fn obstacles(&self, seed: u64, rows: u32, cols: u32) -> Vec<Vec<bool>>;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum GeneratorKind {
+    Perlin,
+    Caves,
+    Maze,
+    Rocks,
+    Open,
+}
+
+impl GeneratorKind {
+    pub fn from_str(generator_str: &str) -> Option<GeneratorKind> {
+        match generator_str.to_lowercase().as_str() {
+            "perlin" => Some(GeneratorKind::Perlin),
+            "caves" => Some(GeneratorKind::Caves),
+            "maze" => Some(GeneratorKind::Maze),
+            "rocks" => Some(GeneratorKind::Rocks),
+            "open" => Some(GeneratorKind::Open),
+            _ => None,
+        }
+    }
+
+    pub fn generator(&self, terrain: TerrainSettings, topology: Topology) -> Box<dyn MapGenerator> {
+        match self {
+            GeneratorKind::Perlin => Box::new(PerlinGenerator { terrain, topology }),
+            GeneratorKind::Caves => Box::new(CavesGenerator { topology }),
+            GeneratorKind::Maze => Box::new(MazeGenerator),
+            GeneratorKind::Rocks => Box::new(RocksGenerator { density: terrain.density, topology }),
+            GeneratorKind::Open => Box::new(OpenGenerator),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Reachability {
+    Carve,
+    Fill,
+}
+
+impl Reachability {
+    pub fn from_str(reachability_str: &str) -> Option<Reachability> {
+        match reachability_str.to_lowercase().as_str() {
+            "carve" => Some(Reachability::Carve),
+            "fill" => Some(Reachability::Fill),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BasePlacement {
+    Center,
+    Random,
+    At(u32, u32),
+}
+
+impl BasePlacement {
+    pub fn from_str(placement_str: &str) -> Option<BasePlacement> {
+        match placement_str.to_lowercase().as_str() {
+            "center" => Some(BasePlacement::Center),
+            "random" => Some(BasePlacement::Random),
+            _ => None,
+        }
+    }
+
+    pub fn location(&self, seed: u64, rows: u32, cols: u32) -> Localization {
+        let margin_x = BASE_MARGIN.min(rows.saturating_sub(1) / 2);
+        let margin_y = BASE_MARGIN.min(cols.saturating_sub(1) / 2);
+        let (x, y) = match *self {
+            BasePlacement::Center => (rows / 2, cols / 2),
+            BasePlacement::Random => {
+                let mut rng = StdRng::seed_from_u64(seed.rotate_left(17) ^ 0xBA5E);
+                (
+                    rng.gen_range(margin_x..=rows.saturating_sub(1) - margin_x),
+                    rng.gen_range(margin_y..=cols.saturating_sub(1) - margin_y),
+                )
+            }
+            BasePlacement::At(x, y) => (x, y),
+        };
+        Localization {
+            x: x.clamp(margin_x, rows.saturating_sub(1) - margin_x),
+            y: y.clamp(margin_y, cols.saturating_sub(1) - margin_y),
+        }
+    }
+}
+
+pub struct PerlinGenerator {
+    pub terrain: TerrainSettings,
+    pub topology: Topology,
+}
+
+impl MapGenerator for PerlinGenerator {
+    fn obstacles(&self, seed: u64, rows: u32, cols: u32) -> Vec<Vec<bool>> {
+        let noise_map = self.terrain.noise_map(seed, rows, cols, self.topology);
+        let threshold = self.terrain.threshold(&noise_map);
+        noise_map.iter()
+            .map(|row| row.iter().map(|value| *value > threshold).collect())
+            .collect()
+    }
+}
+
+pub struct CavesGenerator {
+    pub topology: Topology,
+}
+
+impl MapGenerator for CavesGenerator {
+    fn obstacles(&self, seed: u64, rows: u32, cols: u32) -> Vec<Vec<bool>> {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let mut grid: Vec<Vec<bool>> = (0..rows)
+            .map(|_| (0..cols).map(|_| rng.gen_bool(CAVE_FILL)).collect())
+            .collect();
+        for _ in 0..CAVE_STEPS {
+            let mut next = grid.clone();
+            for i in 0..rows as i32 {
+                for j in 0..cols as i32 {
+                    let mut walls = 0;
+                    for di in -1..=1 {
+                        for dj in -1..=1 {
+                            if di == 0 && dj == 0 {
+                                continue;
+                            }
+                            let neighbour = self.topology.wrap(i + di, j + dj, rows, cols);
+                            if neighbour.map_or(true, |(ni, nj)| grid[ni as usize][nj as usize]) {
+                                walls += 1;
+                            }
+                        }
+                    }
+                    next[i as usize][j as usize] = walls >= 5;
+                }
+            }
+            grid = next;
+        }
+        grid
+    }
+}
+
+pub struct MazeGenerator;
+
+impl MazeGenerator {
+    fn divide(grid: &mut [Vec<bool>], rng: &mut StdRng, top: usize, left: usize, bottom: usize, right: usize) {
+        let height = bottom - top + 1;
+        let width = right - left + 1;
+        if height < 3 || width < 3 {
+            return;
+        }
+        let horizontal = match height.cmp(&width) {
+            std::cmp::Ordering::Greater => true,
+            std::cmp::Ordering::Less => false,
+            std::cmp::Ordering::Equal => rng.gen(),
+        };
+        if horizontal {
+            let row = top + 1 + 2 * rng.gen_range(0..(height - 1) / 2);
+            let gap = left + 2 * rng.gen_range(0..=(width - 1) / 2);
+            for (y, cell) in grid[row].iter_mut().enumerate().take(right + 1).skip(left) {
+                *cell = y != gap;
+            }
+            Self::divide(grid, rng, top, left, row - 1, right);
+            Self::divide(grid, rng, row + 1, left, bottom, right);
+        } else {
+            let col = left + 1 + 2 * rng.gen_range(0..(width - 1) / 2);
+            let gap = top + 2 * rng.gen_range(0..=(height - 1) / 2);
+            for (x, row) in grid.iter_mut().enumerate().take(bottom + 1).skip(top) {
+                row[col] = x != gap;
+            }
+            Self::divide(grid, rng, top, left, bottom, col - 1);
+            Self::divide(grid, rng, top, col + 1, bottom, right);
+        }
+    }
+}
+
+impl MapGenerator for MazeGenerator {
+    fn obstacles(&self, seed: u64, rows: u32, cols: u32) -> Vec<Vec<bool>> {
+        let mut grid = vec![vec![false; cols as usize]; rows as usize];
+        if rows > 0 && cols > 0 {
+            let mut rng = StdRng::seed_from_u64(seed);
+            Self::divide(&mut grid, &mut rng, 0, 0, rows as usize - 1, cols as usize - 1);
+        }
+        grid
+    }
+}
+
+pub struct RocksGenerator {
+    pub density: f64,
+    pub topology: Topology,
+}
+
+impl MapGenerator for RocksGenerator {
+    fn obstacles(&self, seed: u64, rows: u32, cols: u32) -> Vec<Vec<bool>> {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let mut grid = vec![vec![false; cols as usize]; rows as usize];
+        let target = (self.density * (rows * cols) as f64) as usize;
+        let mut placed = 0;
+        for _ in 0..rows * cols {
+            if placed >= target {
+                break;
+            }
+            let center_x = rng.gen_range(0..rows) as i32;
+            let center_y = rng.gen_range(0..cols) as i32;
+            let radius = rng.gen_range(0..=ROCK_MAX_RADIUS);
+            for i in center_x - radius..=center_x + radius {
+                for j in center_y - radius..=center_y + radius {
+                    let Some((x, y)) = self.topology.wrap(i, j, rows, cols) else {
+                        continue;
+                    };
+                    let inside = (i - center_x).pow(2) + (j - center_y).pow(2) <= radius.pow(2) + radius;
+                    if inside && !grid[x as usize][y as usize] {
+                        grid[x as usize][y as usize] = true;
+                        placed += 1;
+                    }
+                }
+            }
+        }
+        grid
+    }
+}
+
+pub struct OpenGenerator;
+
+impl MapGenerator for OpenGenerator {
+    fn obstacles(&self, _seed: u64, rows: u32, cols: u32) -> Vec<Vec<bool>> {
+        vec![vec![false; cols as usize]; rows as usize]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KINDS: [GeneratorKind; 5] = [
+        GeneratorKind::Perlin,
+        GeneratorKind::Caves,
+        GeneratorKind::Maze,
+        GeneratorKind::Rocks,
+        GeneratorKind::Open,
+    ];
+
+    fn generate(kind: GeneratorKind, seed: u64, rows: u32, cols: u32) -> Vec<Vec<bool>> {
+        kind.generator(TerrainSettings::default(), Topology::Bounded).obstacles(seed, rows, cols)
+    }
+
+    fn free_components(grid: &[Vec<bool>]) -> usize {
+        let (rows, cols) = (grid.len(), grid[0].len());
+        let mut seen = vec![vec![false; cols]; rows];
+        let mut components = 0;
+        for x in 0..rows {
+            for y in 0..cols {
+                if grid[x][y] || seen[x][y] {
+                    continue;
+                }
+                components += 1;
+                seen[x][y] = true;
+                let mut stack = vec![(x, y)];
+                while let Some((x, y)) = stack.pop() {
+                    let neighbours = [(x.wrapping_sub(1), y), (x + 1, y), (x, y.wrapping_sub(1)), (x, y + 1)];
+                    for (next_x, next_y) in neighbours {
+                        if next_x < rows && next_y < cols && !grid[next_x][next_y] && !seen[next_x][next_y] {
+                            seen[next_x][next_y] = true;
+                            stack.push((next_x, next_y));
+                        }
+                    }
+                }
+            }
+        }
+        components
+    }
+
+    #[test]
+    fn generators_are_deterministic_for_a_seed() {
+        for kind in KINDS {
+            for seed in [0, 7, 123456789] {
+                let grid = generate(kind, seed, 20, 30);
+                assert_eq!(grid.len(), 20, "{:?}", kind);
+                assert!(grid.iter().all(|row| row.len() == 30), "{:?}", kind);
+                assert_eq!(grid, generate(kind, seed, 20, 30), "{:?} seed {}", kind, seed);
+            }
+        }
+    }
+
+    #[test]
+    fn seeded_generators_change_with_the_seed() {
+        for kind in [GeneratorKind::Perlin, GeneratorKind::Caves, GeneratorKind::Maze, GeneratorKind::Rocks] {
+            assert_ne!(generate(kind, 1, 20, 30), generate(kind, 2, 20, 30), "{:?}", kind);
+        }
+        assert!(generate(GeneratorKind::Open, 1, 20, 30).iter().flatten().all(|&obstacle| !obstacle));
+    }
+
+    #[test]
+    fn maze_free_cells_are_connected() {
+        for (rows, cols) in [(15, 15), (16, 20), (31, 47), (200, 200)] {
+            for seed in 0..20 {
+                let grid = generate(GeneratorKind::Maze, seed, rows, cols);
+                assert!(grid.iter().flatten().any(|&obstacle| obstacle));
+                assert_eq!(free_components(&grid), 1, "{}x{} seed {}", rows, cols, seed);
+            }
+        }
+    }
+}
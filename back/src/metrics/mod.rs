@@ -0,0 +1,253 @@
+use serde::{Deserialize, Serialize};
+use crate::game::Cell;
+use crate::flow_field::FlowField;
+use crate::resources::{Resource, ResourceRegistry};
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ResourceDistance {
+    pub kind: String,
+    pub x: u32,
+    pub y: u32,
+    pub distance: Option<u32>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct MapMetrics {
+    pub obstacle_density: f32,
+    pub reachable_area: usize,
+    pub reachable_fraction: f32,
+    pub resource_distances: Vec<ResourceDistance>,
+    pub mean_resource_distance: Option<f32>,
+    pub max_resource_distance: Option<u32>,
+    pub chokepoints: usize,
+}
+
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+pub struct MapAcceptance {
+    pub min_obstacle_density: Option<f32>,
+    pub max_obstacle_density: Option<f32>,
+    pub min_reachable_area: Option<usize>,
+    pub min_reachable_fraction: Option<f32>,
+    pub max_resource_distance: Option<u32>,
+    pub max_mean_resource_distance: Option<f32>,
+    pub max_chokepoints: Option<usize>,
+    pub max_attempts: Option<u32>,
+}
+
+const DIRECTIONS: [(i32, i32); 4] = [(-1, 0), (1, 0), (0, -1), (0, 1)];
+const DEFAULT_ATTEMPTS: u32 = 10;
+
+fn count_chokepoints(base_flow: &FlowField) -> usize {
+    let rows = base_flow.distances.len();
+    let cols = base_flow.distances.first().map_or(0, |row| row.len());
+    let passable = |index: usize| base_flow.distances[index / cols][index % cols].is_some();
+    let neighbour = |index: usize, direction: usize| -> Option<usize> {
+        let (dx, dy) = DIRECTIONS[direction];
+        base_flow.topology.wrap((index / cols) as i32 + dx, (index % cols) as i32 + dy, rows as u32, cols as u32)
+            .map(|(x, y)| x as usize * cols + y as usize)
+    };
+
+    let cells = rows * cols;
+    let mut discovery = vec![0u32; cells];
+    let mut low = vec![0u32; cells];
+    let mut parent = vec![usize::MAX; cells];
+    let mut chokepoint = vec![false; cells];
+    let mut timer = 1;
+    for root in 0..cells {
+        if discovery[root] != 0 || !passable(root) {
+            continue;
+        }
+        discovery[root] = timer;
+        low[root] = timer;
+        timer += 1;
+        let mut root_children = 0;
+        let mut stack = vec![(root, 0)];
+        while let Some(&mut (current, ref mut direction)) = stack.last_mut() {
+            if *direction < DIRECTIONS.len() {
+                let next = neighbour(current, *direction);
+                *direction += 1;
+                let Some(next) = next.filter(|&next| passable(next)) else {
+                    continue;
+                };
+                if discovery[next] == 0 {
+                    parent[next] = current;
+                    discovery[next] = timer;
+                    low[next] = timer;
+                    timer += 1;
+                    if current == root {
+                        root_children += 1;
+                    }
+                    stack.push((next, 0));
+                } else if next != parent[current] {
+                    low[current] = low[current].min(discovery[next]);
+                }
+            } else {
+                stack.pop();
+                if let Some(&(previous, _)) = stack.last() {
+                    low[previous] = low[previous].min(low[current]);
+                    if previous != root && low[current] >= discovery[previous] {
+                        chokepoint[previous] = true;
+                    }
+                }
+            }
+        }
+        if root_children > 1 {
+            chokepoint[root] = true;
+        }
+    }
+    chokepoint.iter().filter(|&&is_chokepoint| is_chokepoint).count()
+}
+
+impl MapAcceptance {
+    pub fn attempts(&self) -> u32 {
+        self.max_attempts.unwrap_or(DEFAULT_ATTEMPTS).clamp(1, 100)
+    }
+
+    pub fn seed_for(&self, seed: u64, attempt: u32) -> u64 {
+        seed.wrapping_add((attempt as u64).wrapping_mul(0x9E37_79B9_7F4A_7C15))
+    }
+
+    pub fn accepts(&self, metrics: &MapMetrics) -> bool {
+        self.min_obstacle_density.map_or(true, |min| metrics.obstacle_density >= min)
+            && self.max_obstacle_density.map_or(true, |max| metrics.obstacle_density <= max)
+            && self.min_reachable_area.map_or(true, |min| metrics.reachable_area >= min)
+            && self.min_reachable_fraction.map_or(true, |min| metrics.reachable_fraction >= min)
+            && self.max_resource_distance.map_or(true, |max| {
+                metrics.resource_distances.iter().all(|resource| resource.distance.is_some_and(|distance| distance <= max))
+            })
+            && self.max_mean_resource_distance.map_or(true, |max| metrics.mean_resource_distance.is_some_and(|mean| mean <= max))
+            && self.max_chokepoints.map_or(true, |max| metrics.chokepoints <= max)
+    }
+}
+
+impl MapMetrics {
+    pub fn measure(
+        map_matrix: &[Vec<Cell>],
+        base_flow: &FlowField,
+        resources: &[Resource],
+        registry: &ResourceRegistry,
+        display_obstacle: char,
+    ) -> Self {
+        let mut cells = 0;
+        let mut obstacles = 0;
+        let mut reachable_area = 0;
+        for (x, row) in map_matrix.iter().enumerate() {
+            for (y, cell) in row.iter().enumerate() {
+                cells += 1;
+                if cell.display == display_obstacle {
+                    obstacles += 1;
+                } else if base_flow.distances[x][y].is_some() {
+                    reachable_area += 1;
+                }
+            }
+        }
+        let walkable = cells - obstacles;
+
+        let mut resource_distances: Vec<ResourceDistance> = resources.iter()
+            .map(|resource| ResourceDistance {
+                kind: registry.get(resource.kind).name.clone(),
+                x: resource.loc.x,
+                y: resource.loc.y,
+                distance: base_flow.distance(resource.loc),
+            })
+            .collect();
+        resource_distances.sort_by_key(|resource| (resource.x, resource.y));
+        let reached: Vec<u32> = resource_distances.iter().filter_map(|resource| resource.distance).collect();
+        let mean_resource_distance = (!reached.is_empty())
+            .then(|| reached.iter().sum::<u32>() as f32 / reached.len() as f32);
+
+        Self {
+            obstacle_density: if cells == 0 { 0.0 } else { obstacles as f32 / cells as f32 },
+            reachable_area,
+            reachable_fraction: if walkable == 0 { 0.0 } else { reachable_area as f32 / walkable as f32 },
+            resource_distances,
+            mean_resource_distance,
+            max_resource_distance: reached.iter().copied().max(),
+            chokepoints: count_chokepoints(base_flow),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::Localization;
+    use crate::topology::Topology;
+
+    fn flow(rows: &[&str], topology: Topology) -> FlowField {
+        let map_matrix: Vec<Vec<Cell>> = rows.iter()
+            .map(|row| row.chars().map(|display| Cell { display, explore: 0 }).collect())
+            .collect();
+        FlowField::new(&map_matrix, Localization { x: 0, y: 0 }, '8', topology)
+    }
+
+    fn metrics() -> MapMetrics {
+        MapMetrics {
+            obstacle_density: 0.2,
+            reachable_area: 100,
+            reachable_fraction: 0.9,
+            resource_distances: vec![
+                ResourceDistance { kind: "crystal".to_string(), x: 1, y: 1, distance: Some(4) },
+                ResourceDistance { kind: "energy".to_string(), x: 2, y: 2, distance: Some(12) },
+            ],
+            mean_resource_distance: Some(8.0),
+            max_resource_distance: Some(12),
+            chokepoints: 3,
+        }
+    }
+
+    #[test]
+    fn open_areas_have_no_chokepoints() {
+        assert_eq!(count_chokepoints(&flow(&["   ", "   ", "   "], Topology::Bounded)), 0);
+    }
+
+    #[test]
+    fn corridor_cells_are_chokepoints() {
+        let corridor = ["     ", "88888"];
+        assert_eq!(count_chokepoints(&flow(&corridor, Topology::Bounded)), 3);
+        assert_eq!(count_chokepoints(&flow(&["     "], Topology::Torus)), 0);
+    }
+
+    #[test]
+    fn narrow_doorways_are_chokepoints() {
+        let narrow = ["  8  ", "     ", "  8  "];
+        assert_eq!(count_chokepoints(&flow(&narrow, Topology::Bounded)), 3);
+        let wide = ["  8  ", "     ", "     ", "  8  "];
+        assert_eq!(count_chokepoints(&flow(&wide, Topology::Bounded)), 0);
+    }
+
+    #[test]
+    fn unreachable_cells_are_ignored() {
+        let split = ["   8 ", "   8 ", "   8 "];
+        assert_eq!(count_chokepoints(&flow(&split, Topology::Bounded)), 0);
+    }
+
+    #[test]
+    fn accepts_checks_every_bound() {
+        assert!(MapAcceptance::default().accepts(&metrics()));
+        let within = MapAcceptance {
+            max_obstacle_density: Some(0.3),
+            min_reachable_area: Some(100),
+            min_reachable_fraction: Some(0.8),
+            max_resource_distance: Some(12),
+            max_mean_resource_distance: Some(8.0),
+            max_chokepoints: Some(3),
+            ..MapAcceptance::default()
+        };
+        assert!(within.accepts(&metrics()));
+        assert!(!MapAcceptance { min_obstacle_density: Some(0.25), ..within }.accepts(&metrics()));
+        assert!(!MapAcceptance { max_resource_distance: Some(11), ..within }.accepts(&metrics()));
+        assert!(!MapAcceptance { max_chokepoints: Some(2), ..within }.accepts(&metrics()));
+
+        let mut unreachable = metrics();
+        unreachable.resource_distances[0].distance = None;
+        assert!(!within.accepts(&unreachable));
+    }
+
+    #[test]
+    fn attempts_are_clamped() {
+        assert_eq!(MapAcceptance::default().attempts(), DEFAULT_ATTEMPTS);
+        assert_eq!(MapAcceptance { max_attempts: Some(0), ..MapAcceptance::default() }.attempts(), 1);
+        assert_eq!(MapAcceptance { max_attempts: Some(1000), ..MapAcceptance::default() }.attempts(), 100);
+    }
+}
@@ -0,0 +1,294 @@
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap, HashSet};
+use crate::game::{Localization, Cell};
+use crate::flow_field::FlowField;
+
+pub const WINDOW: u32 = 16;
+
+const DIRECTIONS: [(i32, i32); 5] = [(0, 0), (-1, 0), (1, 0), (0, -1), (0, 1)];
+
+pub struct SpaceTimeTable {
+    pub now: u32,
+    shared_display: Option<char>,
+    cells: HashMap<(u32, u32, u32), u32>,
+    by_robot: HashMap<u32, Vec<(u32, u32, u32)>>,
+    priorities: HashMap<u32, u64>,
+    invalidated: HashSet<u32>,
+}
+
+impl SpaceTimeTable {
+    pub fn new() -> Self {
+        Self {
+            now: 0,
+            shared_display: None,
+            cells: HashMap::new(),
+            by_robot: HashMap::new(),
+            priorities: HashMap::new(),
+            invalidated: HashSet::new(),
+        }
+    }
+
+    pub fn advance(&mut self, now: u32, shared_display: Option<char>) {
+        self.now = now;
+        self.shared_display = shared_display;
+        self.cells.retain(|&(_, _, t), _| t >= now);
+        for reserved in self.by_robot.values_mut() {
+            reserved.retain(|&(_, _, t)| t >= now);
+        }
+    }
+
+    pub fn set_priority(&mut self, robot_id: u32, priority: u64) {
+        self.priorities.insert(robot_id, priority);
+    }
+
+    pub fn priority(&self, robot_id: u32) -> u64 {
+        self.priorities.get(&robot_id).copied().unwrap_or(0)
+    }
+
+    pub fn release(&mut self, robot_id: u32) {
+        if let Some(reserved) = self.by_robot.remove(&robot_id) {
+            for key in reserved {
+                if self.cells.get(&key) == Some(&robot_id) {
+                    self.cells.remove(&key);
+                }
+            }
+        }
+    }
+
+    pub fn take_invalidated(&mut self, robot_id: u32) -> bool {
+        self.invalidated.remove(&robot_id)
+    }
+
+    pub fn is_free(&self, robot_id: u32, loc: Localization, t: u32, map_matrix: &[Vec<Cell>]) -> bool {
+        if self.is_shared(loc, map_matrix) {
+            return true;
+        }
+        match self.cells.get(&(loc.x, loc.y, t)) {
+            Some(&other) => other == robot_id || self.priority(other) < self.priority(robot_id),
+            None => true,
+        }
+    }
+
+    pub fn blocked_cells(&self, robot_id: u32, t: u32, map_matrix: &[Vec<Cell>]) -> Vec<Localization> {
+        self.cells.keys()
+            .filter(|&&(_, _, time)| time == t)
+            .map(|&(x, y, _)| Localization { x, y })
+            .filter(|&loc| !self.is_free(robot_id, loc, t, map_matrix))
+            .collect()
+    }
+
+    pub fn hold(&mut self, robot_id: u32, loc: Localization, t: u32, map_matrix: &[Vec<Cell>]) {
+        if self.is_free(robot_id, loc, t, map_matrix) {
+            self.reserve(robot_id, loc, t, map_matrix);
+        }
+    }
+
+    pub fn plan(
+        &mut self,
+        robot_id: u32,
+        start: Localization,
+        depart: u32,
+        map_matrix: &[Vec<Cell>],
+        heuristic: &FlowField,
+        display_obstacle: char,
+    ) -> Option<Vec<Localization>> {
+        self.release(robot_id);
+        heuristic.distance(start)?;
+
+        let rows = map_matrix.len() as u32;
+        let cols = map_matrix[0].len() as u32;
+        let max_expansions = (rows * cols) as usize * 4;
+
+        let mut open = BinaryHeap::new();
+        let mut closed: HashSet<(u32, u32, u32)> = HashSet::new();
+        let mut parents: HashMap<(u32, u32, u32), (u32, u32, u32)> = HashMap::new();
+        let mut costs: HashMap<(u32, u32, u32), u32> = HashMap::new();
+        let start_key = (start.x, start.y, 0);
+        costs.insert(start_key, 0);
+        open.push(Reverse((heuristic.distance(start).unwrap_or(0), 0, start_key)));
+
+        let mut arrival = None;
+        while let Some(Reverse((_, g, key))) = open.pop() {
+            if !closed.insert(key) {
+                continue;
+            }
+            let (x, y, t) = key;
+            if heuristic.distance(Localization { x, y }) == Some(0) {
+                arrival = Some(key);
+                break;
+            }
+            if closed.len() > max_expansions {
+                break;
+            }
+
+            for &(dx, dy) in &DIRECTIONS {
+                if (dx, dy) == (0, 0) && t >= WINDOW {
+                    continue;
+                }
+                let Some(next) = heuristic.topology.offset(Localization { x, y }, dx, dy, rows, cols) else {
+                    continue;
+                };
+                if map_matrix[next.x as usize][next.y as usize].display == display_obstacle {
+                    continue;
+                }
+                let next_t = (t + 1).min(WINDOW);
+                if t < WINDOW && !self.can_move(robot_id, Localization { x, y }, next, depart + t, map_matrix) {
+                    continue;
+                }
+                let next_key = (next.x, next.y, next_t);
+                if closed.contains(&next_key) || costs.get(&next_key).is_some_and(|&cost| cost <= g + 1) {
+                    continue;
+                }
+                if let Some(h) = heuristic.distance(next) {
+                    costs.insert(next_key, g + 1);
+                    parents.insert(next_key, key);
+                    open.push(Reverse((g + 1 + h, g + 1, next_key)));
+                }
+            }
+        }
+
+        let mut current = arrival?;
+        let mut path = Vec::new();
+        while current != start_key {
+            path.push(Localization { x: current.0, y: current.1 });
+            current = *parents.get(&current)?;
+        }
+        path.reverse();
+
+        self.reserve(robot_id, start, depart, map_matrix);
+        for (i, loc) in path.iter().enumerate() {
+            let t = depart + i as u32 + 1;
+            if t > depart + WINDOW {
+                break;
+            }
+            self.reserve(robot_id, *loc, t, map_matrix);
+        }
+        Some(path)
+    }
+
+    fn can_move(&self, robot_id: u32, from: Localization, to: Localization, t: u32, map_matrix: &[Vec<Cell>]) -> bool {
+        if !self.is_free(robot_id, to, t + 1, map_matrix) {
+            return false;
+        }
+        if from.same_loc(&to) || self.is_shared(to, map_matrix) || self.is_shared(from, map_matrix) {
+            return true;
+        }
+        match (self.cells.get(&(to.x, to.y, t)), self.cells.get(&(from.x, from.y, t + 1))) {
+            (Some(&first), Some(&second)) if first == second && first != robot_id => {
+                self.priority(first) < self.priority(robot_id)
+            }
+            _ => true,
+        }
+    }
+
+    fn is_shared(&self, loc: Localization, map_matrix: &[Vec<Cell>]) -> bool {
+        self.shared_display.is_some_and(|display| map_matrix[loc.x as usize][loc.y as usize].display == display)
+    }
+
+    fn reserve(&mut self, robot_id: u32, loc: Localization, t: u32, map_matrix: &[Vec<Cell>]) {
+        if self.is_shared(loc, map_matrix) {
+            return;
+        }
+        let key = (loc.x, loc.y, t);
+        if let Some(other) = self.cells.insert(key, robot_id) {
+            if other != robot_id {
+                self.release(other);
+                self.cells.insert(key, robot_id);
+                self.invalidated.insert(other);
+            }
+        }
+        self.by_robot.entry(robot_id).or_default().push(key);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::topology::Topology;
+
+    fn open_map(rows: usize, cols: usize) -> Vec<Vec<Cell>> {
+        vec![vec![Cell { display: ' ', explore: 0 }; cols]; rows]
+    }
+
+    fn to(x: u32, y: u32) -> Localization {
+        Localization { x, y }
+    }
+
+    fn cells(path: &[Localization]) -> Vec<(u32, u32)> {
+        path.iter().map(|loc| (loc.x, loc.y)).collect()
+    }
+
+    #[test]
+    fn plan_reaches_the_target_around_obstacles_and_reserves_the_path() {
+        let mut map_matrix = open_map(3, 5);
+        map_matrix[1][2].display = '8';
+        let heuristic = FlowField::new(&map_matrix, to(1, 4), '8', Topology::Bounded);
+        let mut table = SpaceTimeTable::new();
+        table.set_priority(1, 2);
+        table.set_priority(2, 1);
+        let path = table.plan(1, to(1, 0), 0, &map_matrix, &heuristic, '8').unwrap();
+        assert_eq!(path.len(), 6);
+        assert_eq!(path.last().map(|loc| (loc.x, loc.y)), Some((1, 4)));
+        assert!(path.iter().all(|loc| !loc.same_loc(&to(1, 2))));
+        for (t, loc) in path.iter().enumerate() {
+            assert!(!table.is_free(2, *loc, t as u32 + 1, &map_matrix));
+        }
+        table.release(1);
+        assert!(table.is_free(2, path[0], 1, &map_matrix));
+    }
+
+    #[test]
+    fn plan_waits_or_detours_around_higher_priority_reservations() {
+        let map_matrix = open_map(1, 4);
+        let heuristic = FlowField::new(&map_matrix, to(0, 3), '8', Topology::Bounded);
+        let mut table = SpaceTimeTable::new();
+        table.set_priority(1, 2);
+        table.set_priority(2, 1);
+        table.hold(1, to(0, 1), 1, &map_matrix);
+        let path = table.plan(2, to(0, 0), 0, &map_matrix, &heuristic, '8').unwrap();
+        assert_eq!(cells(&path), vec![(0, 0), (0, 1), (0, 2), (0, 3)]);
+    }
+
+    #[test]
+    fn can_move_refuses_swapping_with_a_higher_priority_robot() {
+        let map_matrix = open_map(1, 4);
+        let mut table = SpaceTimeTable::new();
+        table.set_priority(1, 2);
+        table.set_priority(2, 1);
+        table.hold(1, to(0, 1), 0, &map_matrix);
+        table.hold(1, to(0, 0), 1, &map_matrix);
+        assert!(!table.can_move(2, to(0, 0), to(0, 1), 0, &map_matrix));
+        table.set_priority(2, 3);
+        assert!(table.can_move(2, to(0, 0), to(0, 1), 0, &map_matrix));
+    }
+
+    #[test]
+    fn higher_priority_plans_invalidate_lower_ones() {
+        let map_matrix = open_map(1, 5);
+        let left = FlowField::new(&map_matrix, to(0, 0), '8', Topology::Bounded);
+        let right = FlowField::new(&map_matrix, to(0, 4), '8', Topology::Bounded);
+        let mut table = SpaceTimeTable::new();
+        table.set_priority(1, 1);
+        table.set_priority(2, 2);
+        table.plan(1, to(0, 2), 0, &map_matrix, &left, '8').unwrap();
+        assert!(!table.is_free(3, to(0, 0), 2, &map_matrix));
+        table.plan(2, to(0, 0), 0, &map_matrix, &right, '8').unwrap();
+        assert!(table.take_invalidated(1));
+        assert!(!table.take_invalidated(1));
+        assert!(!table.take_invalidated(2));
+        assert!(table.is_free(3, to(0, 0), 2, &map_matrix));
+        assert!(!table.is_free(3, to(0, 2), 2, &map_matrix));
+    }
+
+    #[test]
+    fn base_tiles_are_never_reserved() {
+        let mut map_matrix = open_map(1, 3);
+        map_matrix[0][1].display = '#';
+        let mut table = SpaceTimeTable::new();
+        table.advance(0, Some('#'));
+        table.set_priority(1, 2);
+        table.hold(1, to(0, 1), 1, &map_matrix);
+        assert!(table.is_free(2, to(0, 1), 1, &map_matrix));
+        assert!(table.blocked_cells(2, 1, &map_matrix).is_empty());
+    }
+}
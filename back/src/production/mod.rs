@@ -0,0 +1,51 @@
+use serde::Serialize;
+use crate::game::Nature;
+
+pub const MAX_QUEUE: usize = 10;
+
+#[derive(Debug, Clone, Copy)]
+pub struct Recipe {
+    pub cost: &'static [(&'static str, u16)],
+    pub build_time: u32,
+}
+
+impl Recipe {
+    pub fn for_nature(nature: Nature) -> Self {
+        match nature {
+            Nature::Scout => Self { cost: &[("crystal", 20), ("energy", 10)], build_time: 5 },
+            Nature::Gatherer => Self { cost: &[("crystal", 30), ("energy", 10)], build_time: 8 },
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct BuildOrder {
+    pub nature: Nature,
+    pub started: bool,
+    pub remaining: u32,
+}
+
+#[derive(Serialize)]
+pub struct BuildState {
+    pub kind: &'static str,
+    pub started: bool,
+    pub remaining: u32,
+}
+
+impl BuildOrder {
+    pub fn new(nature: Nature) -> Self {
+        Self {
+            nature,
+            started: false,
+            remaining: Recipe::for_nature(nature).build_time,
+        }
+    }
+
+    pub fn state(&self) -> BuildState {
+        BuildState {
+            kind: self.nature.name(),
+            started: self.started,
+            remaining: self.remaining,
+        }
+    }
+}
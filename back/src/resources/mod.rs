@@ -1,31 +1,171 @@
+use std::collections::HashMap;
+use std::fmt;
+use serde::Deserialize;
 use crate::id_generator::IDGenerator;
 use crate::game::*;
 
+pub type Stock = Vec<u64>;
+pub type Cargo = Vec<u16>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ResourceKind(pub usize);
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ResourceType {
+    pub name: String,
+    pub glyph: char,
+    pub quantity: u16,
+    #[serde(default = "default_difficulty")]
+    pub difficulty: f32,
+    #[serde(default = "default_spawn_weight")]
+    pub spawn_weight: u32,
+    #[serde(default)]
+    pub fuel: bool,
+    pub cargo_limit: Option<u16>,
+}
+
+fn default_difficulty() -> f32 {
+    1.0
+}
+
+fn default_spawn_weight() -> u32 {
+    1
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct ResourceRule {
+    pub count: Option<u8>,
+    pub quantity: Option<(u16, u16)>,
+    pub min_base_distance: Option<u32>,
+    pub max_base_distance: Option<u32>,
+    #[serde(default)]
+    pub spacing: u32,
+    #[serde(default)]
+    pub veins: f32,
+    pub regrow_every: Option<u32>,
+    pub respawn_after: Option<u32>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum PlacementError {
+    UnknownKind(String),
+    NoFreeCell(String),
+}
+
+impl fmt::Display for PlacementError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PlacementError::UnknownKind(name) => write!(f, "Unknown resource kind : {}", name),
+            PlacementError::NoFreeCell(name) => write!(f, "No free localization for resource : {}", name),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ResourceRegistry {
+    pub types: Vec<ResourceType>,
+}
+
+impl Default for ResourceRegistry {
+    fn default() -> Self {
+        Self {
+            types: vec![
+                ResourceType { name: "crystal".to_string(), glyph: 'C', quantity: 40, difficulty: 1.0, spawn_weight: 1, fuel: false, cargo_limit: None },
+                ResourceType { name: "energy".to_string(), glyph: 'E', quantity: 40, difficulty: 1.0, spawn_weight: 1, fuel: true, cargo_limit: None },
+            ],
+        }
+    }
+}
+
+impl ResourceRegistry {
+    pub fn new(mut types: Vec<ResourceType>) -> Result<Self, &'static str> {
+        if types.is_empty() {
+            return Err("At least one resource type is required.");
+        }
+        for (i, resource_type) in types.iter().enumerate() {
+            if resource_type.difficulty <= 0.0 {
+                return Err("Resource difficulty must be positive.");
+            }
+            if types[..i].iter().any(|other| other.name.eq_ignore_ascii_case(&resource_type.name)) {
+                return Err("Resource names must be unique.");
+            }
+            if types[..i].iter().any(|other| other.glyph == resource_type.glyph) {
+                return Err("Resource glyphs must be unique.");
+            }
+        }
+        match types.iter().filter(|resource_type| resource_type.fuel).count() {
+            0 => match types.iter_mut().find(|resource_type| resource_type.name.eq_ignore_ascii_case("energy")) {
+                Some(energy) => energy.fuel = true,
+                None => return Err("One resource type must be the fuel."),
+            },
+            1 => {}
+            _ => return Err("Only one resource type can be the fuel."),
+        }
+        Ok(Self { types })
+    }
+
+    pub fn check_glyphs(&self, reserved: &[char]) -> Result<(), &'static str> {
+        if self.types.iter().any(|resource_type| reserved.contains(&resource_type.glyph)) {
+            return Err("Resource glyphs must differ from the display characters, '@' and '.'.");
+        }
+        Ok(())
+    }
+
+    pub fn len(&self) -> usize {
+        self.types.len()
+    }
+
+    pub fn get(&self, kind: ResourceKind) -> &ResourceType {
+        &self.types[kind.0]
+    }
+
+    pub fn kind(&self, name: &str) -> Option<ResourceKind> {
+        self.types.iter()
+            .position(|resource_type| resource_type.name.eq_ignore_ascii_case(name))
+            .map(ResourceKind)
+    }
+
+    pub fn fuel(&self) -> Option<ResourceKind> {
+        self.types.iter().position(|resource_type| resource_type.fuel).map(ResourceKind)
+    }
+
+    pub fn cargo_limits(&self) -> Vec<Option<u16>> {
+        self.types.iter().map(|resource_type| resource_type.cargo_limit).collect()
+    }
+
+    pub fn empty_stock(&self) -> Stock {
+        vec![0; self.types.len()]
+    }
+
+    pub fn resolves(&self, cost: &[(&str, u16)]) -> bool {
+        cost.iter().all(|&(name, _)| self.kind(name).is_some())
+    }
+
+    pub fn resolve(&self, cost: &[(&str, u16)]) -> Vec<(ResourceKind, u16)> {
+        cost.iter()
+            .filter_map(|&(name, amount)| self.kind(name).map(|kind| (kind, amount)))
+            .collect()
+    }
+
+    pub fn named(&self, stock: &[u64]) -> HashMap<String, u64> {
+        self.types.iter()
+            .zip(stock.iter())
+            .map(|(resource_type, amount)| (resource_type.name.clone(), *amount))
+            .collect()
+    }
+}
+
 #[derive(Clone, Copy)]
 pub struct Resource {
     pub id: u32,
     pub loc: Localization,
     pub display: char,
     pub kind: ResourceKind,
+    pub difficulty: f32,
     pub initial_quantity: u16,
     pub remaining_quantity: u16,
 }
 
-#[derive(Debug, Clone, Copy)]
-pub enum ResourceKind {
-    Crystal,
-    Energy,
-}
-impl ResourceKind {
-    pub fn from_str(ressource_kind_str: &str) -> Option<ResourceKind> {
-        match ressource_kind_str.to_lowercase().as_str() {
-            "crystal" => Some(ResourceKind::Crystal),
-            "energy" => Some(ResourceKind::Energy),
-            _ => None,
-        }
-    }
-}
-
 // pub trait CrystalOperations {
 //     fn dig(&mut self);
 // }
@@ -36,55 +176,107 @@ impl ResourceKind {
 
 // pub trait ResourceOperations: CrystalOperations + EnergyOperations {
 pub trait ResourceOperations {
-    fn calculate_gather(&mut self, qt: u16, gatherer_rate: f32) -> u16 ;
-    fn gather(&mut self, qt: u16, gatherer_rate: f32) -> (u16,u16) ;
+    fn calculate_gather(&mut self, qt: u16, gatherer_rate: f32, limit: u16) -> u16 ;
+    fn gather(&mut self, qt: u16, gatherer_rate: f32, limit: u16) -> (ResourceKind, u16) ;
 }
 
 impl ResourceOperations for Resource {
-    fn calculate_gather(&mut self, qt: u16, gatherer_rate: f32) -> u16 {
-        let max_extractable = (qt as f32 * gatherer_rate).round() as u16;
-        let extracted = if self.remaining_quantity < max_extractable {
+    fn calculate_gather(&mut self, qt: u16, gatherer_rate: f32, limit: u16) -> u16 {
+        let max_extractable = ((qt as f32 * gatherer_rate / self.difficulty).round().max(1.0) as u16).min(limit);
+        if self.remaining_quantity < max_extractable {
             let extracted = self.remaining_quantity;
             self.remaining_quantity = 0;
             extracted
         } else {
             self.remaining_quantity -= max_extractable;
             max_extractable
-        };
-        extracted
-    }
-    fn gather(&mut self, qt: u16, gatherer_rate: f32) -> (u16, u16) {
-        let qty = self.calculate_gather(qt, gatherer_rate);
-    
-        match self.kind {
-            ResourceKind::Crystal => (qty, 0),
-            ResourceKind::Energy => (0, qty),
         }
     }
+    fn gather(&mut self, qt: u16, gatherer_rate: f32, limit: u16) -> (ResourceKind, u16) {
+        let qty = self.calculate_gather(qt, gatherer_rate, limit);
+        (self.kind, qty)
+    }
 }
 
 impl Resource {
     pub fn new_resource(
         loc: Localization,
         kind: ResourceKind,
+        resource_type: &ResourceType,
         initial_quantity: u16,
         id_generator: &mut IDGenerator,
     ) -> Option<Self> {
         let id = id_generator.generate_id();
-        let display = match kind {
-            ResourceKind::Crystal => 'C',
-            ResourceKind::Energy => 'E',
-        };
 
         Some(
             Self {
                 id,
                 loc,
                 kind,
-                display,
-                initial_quantity: initial_quantity,
+                display: resource_type.glyph,
+                difficulty: resource_type.difficulty,
+                initial_quantity,
                 remaining_quantity: initial_quantity,
             }
         )
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn resource(difficulty: f32, quantity: u16) -> Resource {
+        let resource_type = ResourceType { name: "ore".to_string(), glyph: 'O', quantity, difficulty, spawn_weight: 1, fuel: false, cargo_limit: None };
+        Resource::new_resource(Localization { x: 0, y: 0 }, ResourceKind(0), &resource_type, quantity, &mut IDGenerator::new()).unwrap()
+    }
+
+    #[test]
+    fn gather_divides_by_difficulty() {
+        let mut ore = resource(2.0, 40);
+        assert_eq!(ore.calculate_gather(10, 1.0, u16::MAX), 5);
+        assert_eq!(ore.remaining_quantity, 35);
+    }
+
+    #[test]
+    fn gather_extracts_at_least_one_unit() {
+        let mut ore = resource(50.0, 3);
+        assert_eq!(ore.calculate_gather(10, 1.0, u16::MAX), 1);
+        assert_eq!(ore.calculate_gather(10, 1.0, 0), 0);
+        assert_eq!(ore.remaining_quantity, 2);
+    }
+
+    #[test]
+    fn gather_stops_at_limit_and_remaining_quantity() {
+        let mut ore = resource(1.0, 12);
+        assert_eq!(ore.calculate_gather(10, 1.0, 4), 4);
+        assert_eq!(ore.calculate_gather(10, 1.0, u16::MAX), 8);
+        assert_eq!(ore.calculate_gather(10, 1.0, u16::MAX), 0);
+    }
+
+    fn resource_type(name: &str, glyph: char, fuel: bool) -> ResourceType {
+        ResourceType { name: name.to_string(), glyph, quantity: 40, difficulty: 1.0, spawn_weight: 1, fuel, cargo_limit: None }
+    }
+
+    #[test]
+    fn energy_is_the_fuel_unless_another_type_is() {
+        let registry = ResourceRegistry::new(vec![resource_type("crystal", 'C', false), resource_type("energy", 'E', false)]).unwrap();
+        assert_eq!(registry.fuel(), Some(ResourceKind(1)));
+        let registry = ResourceRegistry::new(vec![resource_type("crystal", 'C', true), resource_type("energy", 'E', false)]).unwrap();
+        assert_eq!(registry.fuel(), Some(ResourceKind(0)));
+    }
+
+    #[test]
+    fn registry_needs_exactly_one_fuel() {
+        assert!(ResourceRegistry::new(vec![resource_type("crystal", 'C', false), resource_type("ore", 'O', false)]).is_err());
+        assert!(ResourceRegistry::new(vec![resource_type("crystal", 'C', true), resource_type("energy", 'E', true)]).is_err());
+    }
+
+    #[test]
+    fn glyphs_cannot_reuse_reserved_symbols() {
+        let registry = ResourceRegistry::new(vec![resource_type("crystal", 'C', false), resource_type("energy", '@', false)]).unwrap();
+        assert!(registry.check_glyphs(&['#', '@', '.']).is_err());
+        assert!(ResourceRegistry::default().check_glyphs(&['#', '@', '.']).is_ok());
+        assert!(ResourceRegistry::default().check_glyphs(&['C']).is_err());
+    }
+}
@@ -1,55 +1,125 @@
 use rand::prelude::*;
-use std::collections::{VecDeque, HashMap};
+use std::collections::HashMap;
 use std::sync::{Arc, RwLock};
 use std::sync::mpsc::{Sender, Receiver};
 
 use crate::id_generator::IDGenerator;
 use crate::events::*;
-use crate::game::{Localization, Cell};
+use crate::game::{Localization, Cell, Base, MOVE_COST, RECHARGE_MARGIN};
+use crate::flow_field::FlowField;
+use crate::planner::SpaceTimeTable;
+use crate::topology::Topology;
+
+const FRONTIER_CLAIM_RADIUS: u32 = 4;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ExplorationMode {
+    Local,
+    Frontier,
+}
+
+impl ExplorationMode {
+    pub fn from_str(mode_str: &str) -> Option<ExplorationMode> {
+        match mode_str.to_lowercase().as_str() {
+            "local" => Some(ExplorationMode::Local),
+            "frontier" => Some(ExplorationMode::Frontier),
+            _ => None,
+        }
+    }
+}
 
 pub struct Scout {
     pub id: u32,
     pub loc: Localization,
-    pub display: char,
-    pub prev_loc: Option<Localization>
+    pub prev_loc: Option<Localization>,
+    pub target: Option<Localization>,
+    pub blocked: Option<Localization>,
+    pub battery: u16,
+    pub battery_capacity: u16,
+    pub recharging: bool,
+    pub topology: Topology,
 }
 
 impl Scout {
-    pub fn new(loc: Localization, id_generator: &mut IDGenerator) -> Option<Self> {
+    pub fn new(loc: Localization, battery_capacity: u16, topology: Topology, id_generator: &mut IDGenerator) -> Option<Self> {
         let id = id_generator.generate_id();
-        let display = 'S';
-
         Some(Self {
             id,
             loc,
             prev_loc: Some(loc),
-            display
+            target: None,
+            blocked: None,
+            battery: battery_capacity,
+            battery_capacity,
+            recharging: false,
+            topology,
         })
     }
 
+    #[allow(clippy::too_many_arguments)]
     pub fn handle_events(
         &mut self, 
         map_matrix: Arc<RwLock<Vec<Vec<Cell>>>>,
         rows: u32, 
         cols: u32, 
         seed: u64,
+        exploration_mode: ExplorationMode,
+        frontier_claims: Arc<RwLock<HashMap<u32, Localization>>>,
+        planner: Arc<RwLock<SpaceTimeTable>>,
+        base_flow: Arc<RwLock<FlowField>>,
+        base: Arc<RwLock<Base>>,
         scout_receiver: Receiver<EventType>, 
         map_sender: Sender<EventType>,
         display_obstacle: char,
     ) {
     
-        loop {
-            if let Ok(event) = scout_receiver.recv() {
-                match event {
-                    EventType::Tick => {
-                        let map_matrix = map_matrix.read().unwrap();
-    
-                        let map_matrix_copy = map_matrix.clone();
-                        self.explore(&map_matrix_copy, rows, cols, seed, display_obstacle);
-                        let _ = map_sender.send(EventType::Moved(self.loc));
+        while let Ok(event) = scout_receiver.recv() {
+            match event {
+                EventType::Tick => {
+                    let map_matrix = map_matrix.read().unwrap();
+
+                    let mut planner = planner.write().unwrap();
+                    let now = planner.now;
+
+                    let mut map_matrix_copy = map_matrix.clone();
+                    if let Some(blocked) = self.blocked.take() {
+                        map_matrix_copy[blocked.x as usize][blocked.y as usize].display = display_obstacle;
                     }
-                    _ => {
+                    for reserved in planner.blocked_cells(self.id, now + 1, &map_matrix) {
+                        map_matrix_copy[reserved.x as usize][reserved.y as usize].display = display_obstacle;
                     }
+                    let base = base.read().unwrap().clone();
+                    let base_flow = base_flow.read().unwrap();
+                    planner.release(self.id);
+                    if self.return_to_base(&mut planner, &map_matrix_copy, &base_flow, &base, display_obstacle) {
+                        if self.target.take().is_some() {
+                            frontier_claims.write().unwrap().remove(&self.id);
+                        }
+                    } else {
+                        match exploration_mode {
+                            ExplorationMode::Local => {
+                                self.explore(&mut planner, &map_matrix_copy, rows, cols, seed, display_obstacle);
+                            }
+                            ExplorationMode::Frontier => {
+                                let mut frontier_claims = frontier_claims.write().unwrap();
+                                self.explore_frontier(&mut planner, &map_matrix_copy, &mut frontier_claims, rows, cols, seed, display_obstacle);
+                            }
+                        }
+                    }
+                    planner.hold(self.id, self.loc, now + 1, &map_matrix);
+                    let _ = map_sender.send(EventType::Moved(self.loc));
+                }
+                EventType::MoveRefused(loc) => {
+                    self.blocked = Some(self.loc);
+                    self.loc = loc;
+                }
+                EventType::Yield => {
+                    self.target = None;
+                }
+                EventType::Battery(level) => {
+                    self.battery = level;
+                }
+                _ => {
                 }
             }
         }
@@ -61,27 +131,129 @@ impl Scout {
         )
     }
 
-    pub fn explore(&mut self, map_matrix: &Vec<Vec<Cell>>, rows: u32, cols: u32, seed: u64, display_obstacle: char) {
+    pub fn explore(&mut self, planner: &mut SpaceTimeTable, map_matrix: &[Vec<Cell>], rows: u32, cols: u32, seed: u64, display_obstacle: char) {
         let mut rng = self.initialize_rng(seed);
-        let circle_cells = get_circle_cells(self.loc.x as i32, self.loc.y as i32, rows as i32, cols as i32);
+        let circle_cells = get_circle_cells(self.loc.x as i32, self.loc.y as i32, rows, cols, self.topology);
 
-        if self.try_move_to_best_cell(&circle_cells, map_matrix, rows, cols, &mut rng, display_obstacle) {
+        if self.try_move_to_best_cell(planner, &circle_cells, map_matrix, &mut rng, display_obstacle) {
             return;
         }
 
-        if self.try_move_to_any_cell(&circle_cells, map_matrix, rows, cols, &mut rng, display_obstacle) {
+        if self.try_move_to_any_cell(planner, &circle_cells, map_matrix, &mut rng, display_obstacle) {
             return;
         }
 
         self.swap_with_previous_location();
     }
 
-    fn try_move_to_best_cell(
+    fn return_to_base(
+        &mut self,
+        planner: &mut SpaceTimeTable,
+        map_matrix: &[Vec<Cell>],
+        base_flow: &FlowField,
+        base: &Base,
+        display_obstacle: char,
+    ) -> bool {
+        if !self.recharging {
+            match base_flow.distance(self.loc) {
+                Some(distance) if distance > 0 && self.battery <= (distance as u16).saturating_mul(MOVE_COST).saturating_add(RECHARGE_MARGIN) => {
+                    self.recharging = true;
+                }
+                _ => return false,
+            }
+        }
+
+        if base_flow.distance(self.loc) == Some(0) {
+            if base.keeps_charging(self.battery, self.battery_capacity) {
+                return true;
+            }
+            self.recharging = false;
+            return false;
+        }
+        self.step_towards(planner, map_matrix, base_flow, display_obstacle);
+        true
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub fn explore_frontier(
         &mut self,
-        circle_cells: &Vec<(i32, i32)>,
-        map_matrix: &Vec<Vec<Cell>>,
+        planner: &mut SpaceTimeTable,
+        map_matrix: &[Vec<Cell>],
+        frontier_claims: &mut HashMap<u32, Localization>,
         rows: u32,
         cols: u32,
+        seed: u64,
+        display_obstacle: char,
+    ) {
+        if let Some(target) = self.target {
+            if target.same_loc(&self.loc) || !is_frontier(target.x as i32, target.y as i32, map_matrix, rows, cols, display_obstacle, self.topology) {
+                self.target = None;
+            }
+        }
+        if self.target.is_none() {
+            self.target = self.find_frontier(map_matrix, frontier_claims, rows, cols, seed, display_obstacle);
+        }
+
+        if let Some(target) = self.target {
+            frontier_claims.insert(self.id, target);
+            let heuristic = FlowField::new(map_matrix, target, display_obstacle, self.topology);
+            if self.step_towards(planner, map_matrix, &heuristic, display_obstacle) {
+                return;
+            }
+            self.target = None;
+        }
+        frontier_claims.remove(&self.id);
+        self.explore(planner, map_matrix, rows, cols, seed, display_obstacle);
+    }
+
+    fn find_frontier(
+        &self,
+        map_matrix: &[Vec<Cell>],
+        frontier_claims: &HashMap<u32, Localization>,
+        rows: u32,
+        cols: u32,
+        seed: u64,
+        display_obstacle: char,
+    ) -> Option<Localization> {
+        let mut rng = self.initialize_rng(seed);
+        let distances = FlowField::new(map_matrix, self.loc, display_obstacle, self.topology);
+        let claimed: Vec<Localization> = frontier_claims.iter()
+            .filter(|&(&scout_id, _)| scout_id != self.id)
+            .map(|(_, &loc)| loc)
+            .collect();
+
+        let mut best_free: (u32, Vec<Localization>) = (u32::MAX, Vec::new());
+        let mut best_claimed: (u32, Vec<Localization>) = (u32::MAX, Vec::new());
+        for x in 0..rows {
+            for y in 0..cols {
+                if !is_frontier(x as i32, y as i32, map_matrix, rows, cols, display_obstacle, self.topology) {
+                    continue;
+                }
+                let loc = Localization { x, y };
+                if let Some(distance) = distances.distance(loc) {
+                    let is_claimed = claimed.iter().any(|other| {
+                        self.topology.manhattan(*other, loc, rows, cols) <= FRONTIER_CLAIM_RADIUS
+                    });
+                    let best = if is_claimed { &mut best_claimed } else { &mut best_free };
+                    if distance < best.0 {
+                        *best = (distance, Vec::new());
+                    }
+                    if distance == best.0 {
+                        best.1.push(loc);
+                    }
+                }
+            }
+        }
+
+        let candidates = if best_free.1.is_empty() { best_claimed.1 } else { best_free.1 };
+        candidates.choose(&mut rng).copied()
+    }
+
+    fn try_move_to_best_cell(
+        &mut self,
+        planner: &mut SpaceTimeTable,
+        circle_cells: &[(i32, i32)],
+        map_matrix: &[Vec<Cell>],
         rng: &mut StdRng,
         display_obstacle: char,
     ) -> bool {
@@ -96,15 +268,14 @@ impl Scout {
             .filter(|&(i, j)| map_matrix[i as usize][j as usize].explore == min_explore && map_matrix[i as usize][j as usize].display != display_obstacle)
             .collect();
 
-        self.attempt_movement(&mut best_cells, map_matrix, rows, cols, rng, display_obstacle)
+        self.attempt_movement(planner, &mut best_cells, map_matrix, rng, display_obstacle)
     }
 
     fn try_move_to_any_cell(
         &mut self,
-        circle_cells: &Vec<(i32, i32)>,
-        map_matrix: &Vec<Vec<Cell>>,
-        rows: u32,
-        cols: u32,
+        planner: &mut SpaceTimeTable,
+        circle_cells: &[(i32, i32)],
+        map_matrix: &[Vec<Cell>],
         rng: &mut StdRng,
         display_obstacle: char,
     ) -> bool {
@@ -113,34 +284,23 @@ impl Scout {
             .filter(|&(i, j)| map_matrix[i as usize][j as usize].display != display_obstacle)
             .collect();
 
-        self.attempt_movement(&mut retry_cells, map_matrix, rows, cols, rng, display_obstacle)
+        self.attempt_movement(planner, &mut retry_cells, map_matrix, rng, display_obstacle)
     }
 
     fn attempt_movement(
         &mut self,
+        planner: &mut SpaceTimeTable,
         cells: &mut Vec<(i32, i32)>,
-        map_matrix: &Vec<Vec<Cell>>,
-        rows: u32,
-        cols: u32,
+        map_matrix: &[Vec<Cell>],
         rng: &mut StdRng,
         display_obstacle: char,
     ) -> bool {
         while !cells.is_empty() {
             if let Some(&(target_x, target_y)) = cells.choose(rng) {
-                if let Some(path) = find_shortest_path(
-                    (self.loc.x as i32, self.loc.y as i32),
-                    (target_x, target_y),
-                    map_matrix,
-                    rows,
-                    cols,
-                    display_obstacle,
-                ) {
-                    for &(step_x, step_y) in &path {
-                        if map_matrix[step_x as usize][step_y as usize].display != display_obstacle {
-                            self.move_to(step_x as u32, step_y as u32);
-                            return true;
-                        }
-                    }
+                let target = Localization { x: target_x as u32, y: target_y as u32 };
+                let heuristic = FlowField::new(map_matrix, target, display_obstacle, self.topology);
+                if self.step_towards(planner, map_matrix, &heuristic, display_obstacle) {
+                    return true;
                 }
                 cells.retain(|&(x, y)| !(x == target_x && y == target_y));
             }
@@ -148,6 +308,19 @@ impl Scout {
         false
     }
 
+    fn step_towards(&mut self, planner: &mut SpaceTimeTable, map_matrix: &[Vec<Cell>], heuristic: &FlowField, display_obstacle: char) -> bool {
+        let depart = planner.now;
+        let path = planner.plan(self.id, self.loc, depart, map_matrix, heuristic, display_obstacle)
+            .or_else(|| heuristic.path_from(self.loc));
+        match path.and_then(|path| path.first().copied()) {
+            Some(next) => {
+                self.move_to(next.x, next.y);
+                true
+            }
+            None => false,
+        }
+    }
+
     fn swap_with_previous_location(&mut self) {
         if let Some(prev) = self.prev_loc {
             self.prev_loc = Some(self.loc);
@@ -162,15 +335,16 @@ impl Scout {
     }
 }
 
-fn get_circle_cells(x: i32, y: i32, rows: i32, cols: i32) -> Vec<(i32, i32)> {
+fn get_circle_cells(x: i32, y: i32, rows: u32, cols: u32, topology: Topology) -> Vec<(i32, i32)> {
     let mut cells = Vec::new();
 
     for i in (x - 2)..=(x + 2) {
         for j in (y - 2)..=(y + 2) {
-            if i >= 0 && i < rows && j >= 0 && j < cols {
-                if (i - x).pow(2) + (j - y).pow(2) == 4 {
-                    cells.push((i, j));
-                }
+            if (i - x).pow(2) + (j - y).pow(2) != 4 {
+                continue;
+            }
+            if let Some((cell_x, cell_y)) = topology.wrap(i, j, rows, cols) {
+                cells.push((cell_x as i32, cell_y as i32));
             }
         }
     }
@@ -178,37 +352,13 @@ fn get_circle_cells(x: i32, y: i32, rows: i32, cols: i32) -> Vec<(i32, i32)> {
     cells
 }
 
-fn find_shortest_path(start: (i32, i32), target: (i32, i32), map_matrix: &Vec<Vec<Cell>>, rows: u32, cols: u32, display_obstacle: char) -> Option<Vec<(i32, i32)>> {
-    let directions = [(1, 0), (-1, 0), (0, 1), (0, -1)];
-    let mut queue = VecDeque::new();
-    let mut came_from: HashMap<(i32, i32), (i32, i32)> = HashMap::new();
-    
-    queue.push_back(start);
-    came_from.insert(start, start);
-
-    while let Some((x, y)) = queue.pop_front() {
-        if (x, y) == target {
-            let mut path = Vec::new();
-            let mut current = target;
-            while current != start {
-                path.push(current);
-                current = came_from[&current];
-            }
-            path.reverse();
-            return Some(path);
-        }
-
-        for &(dx, dy) in &directions {
-            let next_x = x + dx;
-            let next_y = y + dy;
-
-            if next_x >= 0 && next_x < rows as i32 && next_y >= 0 && next_y < cols as i32 {
-                if map_matrix[next_x as usize][next_y as usize].display != display_obstacle && !came_from.contains_key(&(next_x, next_y)) {
-                    queue.push_back((next_x, next_y));
-                    came_from.insert((next_x, next_y), (x, y));
-                }
-            }
-        }
+fn is_frontier(x: i32, y: i32, map_matrix: &[Vec<Cell>], rows: u32, cols: u32, display_obstacle: char, topology: Topology) -> bool {
+    let cell = &map_matrix[x as usize][y as usize];
+    if cell.explore == -1 || cell.display == display_obstacle {
+        return false;
     }
-    None
+    [(1, 0), (-1, 0), (0, 1), (0, -1)].iter().any(|&(dx, dy)| {
+        topology.wrap(x + dx, y + dy, rows, cols)
+            .is_some_and(|(next_x, next_y)| map_matrix[next_x as usize][next_y as usize].explore == -1)
+    })
 }
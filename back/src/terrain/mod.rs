@@ -0,0 +1,104 @@
+use serde::Deserialize;
+use noise::{Fbm, MultiFractal, NoiseFn, Seedable};
+use crate::topology::Topology;
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+pub struct TerrainSettings {
+    pub scale: Option<f64>,
+    #[serde(default = "default_octaves")]
+    pub octaves: usize,
+    #[serde(default = "default_persistence")]
+    pub persistence: f64,
+    #[serde(default = "default_lacunarity")]
+    pub lacunarity: f64,
+    #[serde(default = "default_density")]
+    pub density: f64,
+    #[serde(default = "default_safe_zone_radius")]
+    pub safe_zone_radius: u32,
+}
+
+fn default_octaves() -> usize {
+    1
+}
+
+fn default_persistence() -> f64 {
+    0.5
+}
+
+fn default_lacunarity() -> f64 {
+    2.0
+}
+
+fn default_density() -> f64 {
+    0.2
+}
+
+fn default_safe_zone_radius() -> u32 {
+    6
+}
+
+impl Default for TerrainSettings {
+    fn default() -> Self {
+        Self {
+            scale: None,
+            octaves: default_octaves(),
+            persistence: default_persistence(),
+            lacunarity: default_lacunarity(),
+            density: default_density(),
+            safe_zone_radius: default_safe_zone_radius(),
+        }
+    }
+}
+
+impl TerrainSettings {
+    pub fn clamped(self, rows: u32, cols: u32) -> Self {
+        Self {
+            scale: self.scale.map(|scale| scale.clamp(1.0, 200.0)),
+            octaves: self.octaves.clamp(1, 8),
+            persistence: self.persistence.clamp(0.0, 1.0),
+            lacunarity: self.lacunarity.clamp(1.0, 4.0),
+            density: self.density.clamp(0.0, 0.6),
+            safe_zone_radius: self.safe_zone_radius.clamp(2, rows.min(cols) / 2),
+        }
+    }
+
+    pub fn noise(&self, seed: u64) -> Fbm {
+        Fbm::new()
+            .set_seed((seed ^ (seed >> 32)) as u32)
+            .set_octaves(self.octaves)
+            .set_persistence(self.persistence)
+            .set_lacunarity(self.lacunarity)
+    }
+
+    pub fn noise_map(&self, seed: u64, rows: u32, cols: u32, topology: Topology) -> Vec<Vec<f64>> {
+        let scale = self.scale.unwrap_or((rows + cols) as f64 / 10.0);
+        let fbm = self.noise(seed);
+        (0..rows).map(|i| {
+            (0..cols).map(|j| {
+                let (x, y) = (i as f64, j as f64);
+                let sample = |x: f64, y: f64| fbm.get([x / scale, y / scale]);
+                match topology {
+                    Topology::Bounded => sample(x, y),
+                    Topology::Torus => {
+                        let (height, width) = (rows as f64, cols as f64);
+                        let (u, v) = (x / height, y / width);
+                        sample(x, y) * (1.0 - u) * (1.0 - v)
+                            + sample(x - height, y) * u * (1.0 - v)
+                            + sample(x, y - width) * (1.0 - u) * v
+                            + sample(x - height, y - width) * u * v
+                    }
+                }
+            }).collect()
+        }).collect()
+    }
+
+    pub fn threshold(&self, noise_map: &[Vec<f64>]) -> f64 {
+        let mut values: Vec<f64> = noise_map.iter().flatten().copied().collect();
+        if values.is_empty() {
+            return f64::MAX;
+        }
+        values.sort_by(|a, b| a.total_cmp(b));
+        let index = ((1.0 - self.density) * values.len() as f64) as usize;
+        values.get(index).copied().unwrap_or(f64::MAX)
+    }
+}
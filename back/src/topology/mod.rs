@@ -0,0 +1,84 @@
+use crate::game::Localization;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Topology {
+    Bounded,
+    Torus,
+}
+
+impl Topology {
+    pub fn from_str(topology_str: &str) -> Option<Topology> {
+        match topology_str.to_lowercase().as_str() {
+            "bounded" => Some(Topology::Bounded),
+            "torus" | "toroidal" => Some(Topology::Torus),
+            _ => None,
+        }
+    }
+
+    pub fn wrap(&self, x: i32, y: i32, rows: u32, cols: u32) -> Option<(u32, u32)> {
+        match self {
+            Topology::Bounded => {
+                (x >= 0 && y >= 0 && x < rows as i32 && y < cols as i32).then_some((x as u32, y as u32))
+            }
+            Topology::Torus => {
+                (rows > 0 && cols > 0).then(|| (x.rem_euclid(rows as i32) as u32, y.rem_euclid(cols as i32) as u32))
+            }
+        }
+    }
+
+    pub fn offset(&self, loc: Localization, dx: i32, dy: i32, rows: u32, cols: u32) -> Option<Localization> {
+        self.wrap(loc.x as i32 + dx, loc.y as i32 + dy, rows, cols)
+            .map(|(x, y)| Localization { x, y })
+    }
+
+    pub fn delta(&self, from: u32, to: u32, size: u32) -> u32 {
+        let direct = from.abs_diff(to);
+        match self {
+            Topology::Bounded => direct,
+            Topology::Torus => direct.min(size.saturating_sub(direct)),
+        }
+    }
+
+    pub fn manhattan(&self, a: Localization, b: Localization, rows: u32, cols: u32) -> u32 {
+        self.delta(a.x, b.x, rows) + self.delta(a.y, b.y, cols)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn from_str_accepts_aliases() {
+        assert_eq!(Topology::from_str("Bounded"), Some(Topology::Bounded));
+        assert_eq!(Topology::from_str("toroidal"), Some(Topology::Torus));
+        assert_eq!(Topology::from_str("sphere"), None);
+    }
+
+    #[test]
+    fn bounded_wrap_rejects_outside_cells() {
+        assert_eq!(Topology::Bounded.wrap(0, 4, 5, 5), Some((0, 4)));
+        assert_eq!(Topology::Bounded.wrap(-1, 0, 5, 5), None);
+        assert_eq!(Topology::Bounded.wrap(0, 5, 5, 5), None);
+    }
+
+    #[test]
+    fn torus_wrap_goes_around_both_axes() {
+        assert_eq!(Topology::Torus.wrap(-1, 5, 5, 5), Some((4, 0)));
+        assert_eq!(Topology::Torus.wrap(11, -7, 5, 5), Some((1, 3)));
+        assert_eq!(Topology::Torus.wrap(0, 0, 0, 5), None);
+        let corner = Localization { x: 0, y: 0 };
+        assert_eq!(Topology::Torus.offset(corner, -1, -1, 4, 6).map(|loc| (loc.x, loc.y)), Some((3, 5)));
+        assert!(Topology::Bounded.offset(corner, -1, 0, 4, 6).is_none());
+    }
+
+    #[test]
+    fn torus_delta_takes_the_shorter_way() {
+        assert_eq!(Topology::Bounded.delta(1, 9, 10), 8);
+        assert_eq!(Topology::Torus.delta(1, 9, 10), 2);
+        assert_eq!(Topology::Torus.delta(2, 7, 10), 5);
+        let (a, b) = (Localization { x: 0, y: 1 }, Localization { x: 9, y: 8 });
+        assert_eq!(Topology::Bounded.manhattan(a, b, 10, 10), 16);
+        assert_eq!(Topology::Torus.manhattan(a, b, 10, 10), 4);
+    }
+}
@@ -0,0 +1,99 @@
+use serde::Serialize;
+use crate::game::Base;
+use crate::resources::{ResourceKind, ResourceRegistry};
+
+pub const MAX_LEVEL: u8 = 3;
+pub const EXTRACT_AMOUNT: u16 = 10;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum UpgradeKind {
+    Cargo,
+    Extraction,
+    Vision,
+    Speed,
+}
+
+impl UpgradeKind {
+    pub const ALL: [UpgradeKind; 4] = [UpgradeKind::Cargo, UpgradeKind::Extraction, UpgradeKind::Vision, UpgradeKind::Speed];
+
+    pub fn from_str(upgrade_str: &str) -> Option<UpgradeKind> {
+        match upgrade_str.to_lowercase().as_str() {
+            "cargo" => Some(UpgradeKind::Cargo),
+            "extraction" => Some(UpgradeKind::Extraction),
+            "vision" => Some(UpgradeKind::Vision),
+            "speed" => Some(UpgradeKind::Speed),
+            _ => None,
+        }
+    }
+
+    pub fn base_cost(&self) -> &'static [(&'static str, u16)] {
+        match self {
+            UpgradeKind::Cargo => &[("crystal", 40), ("energy", 10)],
+            UpgradeKind::Extraction => &[("crystal", 40), ("energy", 20)],
+            UpgradeKind::Vision => &[("crystal", 30), ("energy", 10)],
+            UpgradeKind::Speed => &[("crystal", 60), ("energy", 30)],
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, Serialize)]
+pub struct Upgrades {
+    pub cargo: u8,
+    pub extraction: u8,
+    pub vision: u8,
+    pub speed: u8,
+}
+
+impl Upgrades {
+    pub fn level(&self, kind: UpgradeKind) -> u8 {
+        match kind {
+            UpgradeKind::Cargo => self.cargo,
+            UpgradeKind::Extraction => self.extraction,
+            UpgradeKind::Vision => self.vision,
+            UpgradeKind::Speed => self.speed,
+        }
+    }
+
+    pub fn cost(&self, kind: UpgradeKind, registry: &ResourceRegistry) -> Option<Vec<(ResourceKind, u16)>> {
+        let level = self.level(kind);
+        if level >= MAX_LEVEL {
+            return None;
+        }
+        let cost = registry.resolve(kind.base_cost()).into_iter()
+            .map(|(resource_kind, amount)| (resource_kind, amount * (level as u16 + 1)))
+            .collect();
+        Some(cost)
+    }
+
+    pub fn purchase(&mut self, kind: UpgradeKind, base: &mut Base, registry: &ResourceRegistry) -> Result<u8, &'static str> {
+        let cost = self.cost(kind, registry).ok_or("Upgrade already at max level.")?;
+        if !base.can_afford(&cost) {
+            return Err("Not enough resources.");
+        }
+        base.pay(&cost);
+        let level = match kind {
+            UpgradeKind::Cargo => &mut self.cargo,
+            UpgradeKind::Extraction => &mut self.extraction,
+            UpgradeKind::Vision => &mut self.vision,
+            UpgradeKind::Speed => &mut self.speed,
+        };
+        *level += 1;
+        Ok(*level)
+    }
+
+    pub fn cargo_capacity(&self) -> u16 {
+        10 + 5 * self.cargo as u16
+    }
+
+    pub fn extraction_rate(&self) -> f32 {
+        1.0 + 0.5 * self.extraction as f32
+    }
+
+    pub fn vision_radius(&self) -> i32 {
+        1 + self.vision as i32
+    }
+
+    pub fn moves_per_tick(&self) -> u32 {
+        1 + self.speed as u32
+    }
+}